@@ -0,0 +1,57 @@
+//! Compares one XOR training epoch on the `Rc`-based `Value` graph and on the tape.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use micrograd_rs::engine::Value;
+use micrograd_rs::nn::{Forward, Module, MLP};
+use micrograd_rs::optim::{Optimizer, Sgd};
+use micrograd_rs::scalar::Scalar;
+use micrograd_rs::tape::Graph;
+use micrograd_rs::viz::load_training_data;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+fn xor_training(c: &mut Criterion) {
+    let samples: Vec<(Vec<f64>, f64)> = load_training_data("xor_data.csv")
+        .expect("failed to load xor_data.csv")
+        .iter()
+        .map(|(x, y)| (x.iter().map(Value::data).collect(), y.data()))
+        .collect();
+
+    let mut group = c.benchmark_group("xor_epoch");
+
+    group.bench_function("value", |b| {
+        let model = MLP::new(2, &[4, 1], &mut StdRng::seed_from_u64(0));
+        let mut optimizer = Sgd::new(model.parameters(), 0.1);
+        b.iter(|| {
+            for (x, y) in &samples {
+                let x: Vec<Value> = x.iter().map(|&v| Value::from(v)).collect();
+                let loss = (model.forward(&x)[0].clone() - *y).pow(2.0);
+                optimizer.zero_grad();
+                loss.backward();
+                optimizer.step();
+            }
+        })
+    });
+
+    group.bench_function("tape", |b| {
+        let model = MLP::new(2, &[4, 1], &mut StdRng::seed_from_u64(0));
+        let mut optimizer = Sgd::new(model.parameters(), 0.1);
+        let mut graph = Graph::new();
+        b.iter(|| {
+            for (x, y) in &samples {
+                graph.clear();
+                let x = x.iter().map(|&v| graph.var(v)).collect();
+                let loss = (model.forward_with(x, &mut |p| graph.param(p))[0] - *y).pow(2.0);
+                optimizer.zero_grad();
+                loss.backward();
+                graph.accumulate_param_grads();
+                optimizer.step();
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, xor_training);
+criterion_main!(benches);
@@ -1,51 +1,52 @@
-use crate::Value;
+use crate::engine::{Float, Value};
 
-impl Value {
+impl<T: Float> Value<T> {
+    /// Renders the graph rooted at this node as an ASCII tree.
+    /// Nodes reachable along several paths are only drawn the first time they are seen.
     pub fn draw_ascii(&self) -> String {
         let mut result = String::new();
+        let labels = self.labels();
         let mut visited = std::collections::HashSet::new();
-        self.draw_ascii_recursive(&mut result, &mut visited, "", true);
-        result
-    }
+        // Explicit stack of (node, prefix, is_last) so deep graphs cannot overflow
+        let mut stack = vec![(self.clone(), String::new(), true)];
 
-    fn draw_ascii_recursive(
-        &self,
-        result: &mut String,
-        visited: &mut std::collections::HashSet<usize>,
-        prefix: &str,
-        is_last: bool,
-    ) {
-        let ptr = self as *const Value as usize;
-        if visited.contains(&ptr) {
-            return;
-        }
-        visited.insert(ptr);
+        while let Some((node, prefix, is_last)) = stack.pop() {
+            if !visited.insert(node.id()) {
+                continue;
+            }
 
-        result.push_str(&format!(
-            "{}{} {}\n",
-            prefix,
-            format!("[{:.4}, {:.4}]", self.data(), self.grad()),
-            self.label()
-        ));
+            result.push_str(&format!(
+                "{}[{:.4}, {:.4}] {}\n",
+                prefix,
+                node.data(),
+                node.grad(),
+                labels[&node.id()]
+            ));
 
-        if !self.prev().is_empty() {
-            let new_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
-            result.push_str(&format!("{}└─ {}\n", new_prefix, self.op()));
+            let prev = node.prev();
+            if !prev.is_empty() {
+                let new_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+                let op = node.op().map(|op| op.to_string()).unwrap_or_default();
+                result.push_str(&format!("{}└─ {}\n", new_prefix, op));
 
-            let child_prefix = format!("{}    ", new_prefix);
-            for (i, child) in self.prev().iter().enumerate() {
-                let connector = if i == self.prev().len() - 1 {
-                    "└──"
-                } else {
-                    "├──"
-                };
-                child.draw_ascii_recursive(
-                    result,
-                    visited,
-                    &format!("{}{}", child_prefix, connector),
-                    i == self.prev().len() - 1,
-                );
+                let child_prefix = format!("{}    ", new_prefix);
+                // Push in reverse so children are drawn in their original order
+                for (i, child) in prev.iter().enumerate().rev() {
+                    let is_last_child = i == prev.len() - 1;
+                    let connector = if is_last_child {
+                        "└──"
+                    } else {
+                        "├──"
+                    };
+                    stack.push((
+                        child.clone(),
+                        format!("{}{}", child_prefix, connector),
+                        is_last_child,
+                    ));
+                }
             }
         }
+
+        result
     }
 }
@@ -0,0 +1,277 @@
+//! Forward-mode automatic differentiation with dual numbers.
+//! A dual number carries a value together with one or more tangents (directional
+//! derivatives), and every operation pushes the tangents forward with the chain rule.
+//! One forward pass therefore yields a Jacobian-vector product, with no graph at all.
+
+use std::{
+    fmt::Display,
+    ops::{Add, Div, Mul, Neg, Sub},
+};
+
+use crate::op::{CustomOpRef, Op};
+use crate::scalar::Scalar;
+
+/// A value paired with `N` tangents, i.e. its derivative along `N` input directions.
+/// The op methods come from the [`Scalar`] trait.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dual<const N: usize = 1> {
+    pub value: f64,
+    pub tangents: [f64; N],
+}
+
+impl<const N: usize> Dual<N> {
+    pub fn new(value: f64, tangents: [f64; N]) -> Self {
+        Self { value, tangents }
+    }
+
+    /// Creates a constant, whose derivative along every direction is zero
+    pub fn constant(value: f64) -> Self {
+        Self::new(value, [0.0; N])
+    }
+
+    /// Creates the `index`-th input variable, seeding its own tangent direction with 1
+    pub fn variable(value: f64, index: usize) -> Self {
+        let mut tangents = [0.0; N];
+        tangents[index] = 1.0;
+        Self::new(value, tangents)
+    }
+
+    /// Applies a user-defined differentiable function to the given inputs.
+    pub fn custom(op: CustomOpRef, inputs: &[Dual<N>]) -> Dual<N> {
+        Dual::apply(Op::Custom(op), inputs)
+    }
+
+    /// Evaluates `op` and pushes every input's tangents through its local derivatives:
+    /// ṫ_out = Σ_i (∂out/∂input_i) ṫ_i
+    fn apply(op: Op, inputs: &[Dual<N>]) -> Dual<N> {
+        let data: Vec<f64> = inputs.iter().map(|d| d.value).collect();
+        let value = op.forward(&data);
+        let mut tangents = [0.0; N];
+        for (input, local_grad) in inputs.iter().zip(op.local_grads(&data, value)) {
+            for (t, input_t) in tangents.iter_mut().zip(input.tangents) {
+                *t += local_grad * input_t;
+            }
+        }
+        Dual { value, tangents }
+    }
+}
+
+impl Dual<1> {
+    /// Creates a dual number with a single tangent
+    pub fn with_tangent(value: f64, tangent: f64) -> Self {
+        Self::new(value, [tangent])
+    }
+
+    /// Returns the derivative along the single tangent direction
+    pub fn tangent(&self) -> f64 {
+        self.tangents[0]
+    }
+}
+
+/// Computes the Jacobian of `f` at `x` in a single forward pass, J[i][j] = ∂f_i/∂x_j.
+/// Input j is seeded with the j-th tangent direction, so each output's tangents form
+/// one row of the Jacobian.
+pub fn jacobian<const N: usize, F>(f: F, x: &[f64; N]) -> Vec<Vec<f64>>
+where
+    F: FnOnce(Vec<Dual<N>>) -> Vec<Dual<N>>,
+{
+    let xs = x
+        .iter()
+        .enumerate()
+        .map(|(i, &xi)| Dual::variable(xi, i))
+        .collect();
+    f(xs).iter().map(|out| out.tangents.to_vec()).collect()
+}
+
+impl<const N: usize> From<f64> for Dual<N> {
+    fn from(value: f64) -> Self {
+        Dual::constant(value)
+    }
+}
+
+/// Implements a binary operator for `Dual op Dual`, `Dual op f64` and `f64 op Dual`.
+macro_rules! impl_binary_op {
+    ($trait:ident, $method:ident, $op:expr) => {
+        impl<const N: usize> $trait for Dual<N> {
+            type Output = Dual<N>;
+            fn $method(self, rhs: Dual<N>) -> Dual<N> {
+                Dual::apply($op, &[self, rhs])
+            }
+        }
+
+        impl<const N: usize> $trait<f64> for Dual<N> {
+            type Output = Dual<N>;
+            fn $method(self, rhs: f64) -> Dual<N> {
+                Dual::apply($op, &[self, Dual::constant(rhs)])
+            }
+        }
+
+        impl<const N: usize> $trait<Dual<N>> for f64 {
+            type Output = Dual<N>;
+            fn $method(self, rhs: Dual<N>) -> Dual<N> {
+                Dual::apply($op, &[Dual::constant(self), rhs])
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add, Op::Add);
+impl_binary_op!(Sub, sub, Op::Sub);
+impl_binary_op!(Mul, mul, Op::Mul);
+impl_binary_op!(Div, div, Op::Div);
+
+impl<const N: usize> Neg for Dual<N> {
+    type Output = Dual<N>;
+    fn neg(self) -> Dual<N> {
+        Dual::apply(Op::Neg, &[self])
+    }
+}
+
+impl<const N: usize> Scalar for Dual<N> {
+    fn constant(x: f64) -> Self {
+        Dual::constant(x)
+    }
+
+    fn value(&self) -> f64 {
+        self.value
+    }
+
+    fn pow(&self, exponent: f64) -> Self {
+        Dual::apply(Op::Pow(exponent), &[*self])
+    }
+
+    fn tanh(&self) -> Self {
+        Dual::apply(Op::Tanh, &[*self])
+    }
+
+    fn relu(&self) -> Self {
+        Dual::apply(Op::Relu, &[*self])
+    }
+
+    fn leaky_relu(&self, alpha: f64) -> Self {
+        Dual::apply(Op::LeakyRelu(alpha), &[*self])
+    }
+
+    fn elu(&self) -> Self {
+        Dual::apply(Op::Elu, &[*self])
+    }
+
+    fn exp(&self) -> Self {
+        Dual::apply(Op::Exp, &[*self])
+    }
+
+    fn ln(&self) -> Self {
+        Dual::apply(Op::Ln, &[*self])
+    }
+
+    fn log2(&self) -> Self {
+        Dual::apply(Op::Log2, &[*self])
+    }
+
+    fn sigmoid(&self) -> Self {
+        Dual::apply(Op::Sigmoid, &[*self])
+    }
+
+    fn softplus(&self) -> Self {
+        Dual::apply(Op::Softplus, &[*self])
+    }
+
+    fn gelu(&self) -> Self {
+        Dual::apply(Op::Gelu, &[*self])
+    }
+
+    fn silu(&self) -> Self {
+        Dual::apply(Op::Silu, &[*self])
+    }
+
+    fn abs(&self) -> Self {
+        Dual::apply(Op::Abs, &[*self])
+    }
+
+    fn sqrt(&self) -> Self {
+        Dual::apply(Op::Sqrt, &[*self])
+    }
+
+    fn sin(&self) -> Self {
+        Dual::apply(Op::Sin, &[*self])
+    }
+
+    fn cos(&self) -> Self {
+        Dual::apply(Op::Cos, &[*self])
+    }
+
+    fn sum(xs: &[Self]) -> Self {
+        if xs.is_empty() {
+            return Dual::constant(0.0);
+        }
+        Dual::apply(Op::Sum, xs)
+    }
+
+    fn dot(a: &[Self], b: &[Self]) -> Self {
+        assert_eq!(
+            a.len(),
+            b.len(),
+            "dot product of vectors of different lengths"
+        );
+        if a.is_empty() {
+            return Dual::constant(0.0);
+        }
+        let inputs: Vec<Self> = a.iter().chain(b).copied().collect();
+        Dual::apply(Op::Dot, &inputs)
+    }
+
+    fn logsumexp(xs: &[Self]) -> Self {
+        if xs.is_empty() {
+            return Dual::constant(f64::NEG_INFINITY);
+        }
+        Dual::apply(Op::LogSumExp, xs)
+    }
+}
+
+impl<const N: usize> Display for Dual<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Dual(value: {}, tangents: {:?})",
+            self.value, self.tangents
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::engine::Value;
+    use crate::functional;
+    use crate::nn::{Forward, MLP};
+
+    #[test]
+    fn jacobian_of_product_and_sine() {
+        let (x, y) = (0.6, -1.5);
+        let j = jacobian(|v| vec![v[0] * v[1], v[0].sin()], &[x, y]);
+        assert_eq!(j, vec![vec![y, x], vec![x.cos(), 0.0]]);
+    }
+
+    #[test]
+    fn mlp_jacobian_matches_reverse_mode() {
+        let model = MLP::new(3, &[4, 4, 2], &mut StdRng::seed_from_u64(0));
+        let x = [0.5, -1.2, 0.8];
+        let forward = jacobian(
+            |xs| model.forward_with(xs, &mut |p| Dual::constant(p.data())),
+            &x,
+        );
+        let leaves: Vec<Value> = x
+            .iter()
+            .map(|&xi| Value::new(xi, None, "x".to_string(), None))
+            .collect();
+        let reverse = functional::jacobian(|x| model.forward(x), &leaves);
+
+        assert_eq!((forward.len(), forward[0].len()), (2, 3));
+        for (f, r) in forward.iter().flatten().zip(reverse.iter().flatten()) {
+            assert!((f - r).abs() < 1e-12, "{:?} != {:?}", forward, reverse);
+        }
+    }
+}
@@ -3,32 +3,53 @@
 //! enabling backpropagation for gradient-based optimization.
 
 use std::{
-    cell::RefCell,
-    collections::HashSet,
+    cell::Cell,
+    collections::{HashMap, HashSet},
     fmt::{Debug, Display},
-    ops::{Add, Div, Mul, Sub},
+    iter::{Product, Sum},
+    ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign},
+};
+
+#[cfg(feature = "sync")]
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(feature = "sync"))]
+use std::{
+    cell::{Ref, RefCell, RefMut},
     rc::Rc,
 };
 
+pub use crate::float::Float;
+pub use crate::op::{CustomOp, CustomOpRef, Op};
 use crate::viz::BackpropViz;
 
 /// A node in the computation graph that tracks both forward computation and gradients for backprop.
 /// Each Value represents a scalar value and its gradient with respect to some loss function.
+///
+/// The scalar type `T` defaults to `f64`; see [`Float`] for using `f32` or a custom type.
+///
+/// By default nodes are `Rc<RefCell<..>>`. With the `sync` feature they are
+/// `Arc<RwLock<..>>` instead, making Value `Send + Sync` so that graphs can be built
+/// on several threads at once (see [`crate::parallel`]).
 #[derive(Clone)]
-pub struct Value(Rc<RefCell<ValueInternal>>);
-
-struct ValueInternal {
-    data: f64,
-    grad: f64,
-    prev: Vec<Value>,
-    op: String,
-    pub label: String,
-    backward_fn: Option<BackwardFn>,
-}
+pub struct Value<T: Float = f64>(Shared<T>);
 
-type BackwardFn = Box<dyn Fn(&ValueInternal) + 'static>;
+#[cfg(not(feature = "sync"))]
+type Shared<T> = Rc<RefCell<ValueInternal<T>>>;
+#[cfg(feature = "sync")]
+type Shared<T> = Arc<RwLock<ValueInternal<T>>>;
 
-impl Value {
+struct ValueInternal<T: Float> {
+    data: T,
+    grad: T,
+    prev: Vec<Value<T>>,
+    op: Option<Op<T>>,
+    /// Explicit label; `None` means the label is derived from the graph on demand
+    pub label: Option<String>,
+    /// Whether backprop computes a gradient for this node
+    requires_grad: bool,
+}
+
+impl<T: Float> Value<T> {
     /// Creates a new Value node in the computation graph.
     ///
     /// # Arguments
@@ -36,304 +57,982 @@ impl Value {
     /// * `children` - Optional input Values used to compute this Value
     /// * `label` - Human-readable name for debugging
     /// * `op` - Optional operation that produced this Value
-    pub fn new(data: f64, children: Option<Vec<Value>>, label: String, op: Option<String>) -> Self {
-        Value(Rc::new(RefCell::new(ValueInternal {
+    ///
+    /// A leaf created here is a variable that requires a gradient, unlike a constant
+    /// from `Value::from`.
+    pub fn new(data: T, children: Option<Vec<Value<T>>>, label: String, op: Option<Op<T>>) -> Self {
+        let prev = children.unwrap_or_default();
+        let requires_grad = prev.is_empty() || prev.iter().any(Value::requires_grad);
+        Value::node(data, prev, op, Some(label), requires_grad)
+    }
+
+    /// Allocates a node, leaving its label to be derived lazily when `label` is `None`.
+    fn node(
+        data: T,
+        prev: Vec<Value<T>>,
+        op: Option<Op<T>>,
+        label: Option<String>,
+        requires_grad: bool,
+    ) -> Self {
+        Value::wrap(ValueInternal {
             data,
-            grad: 0.0,
-            prev: children.unwrap_or_default(),
-            op: op.unwrap_or_default(),
+            grad: T::zero(),
+            prev,
+            op,
             label,
-            backward_fn: None,
-        })))
+            requires_grad,
+        })
     }
 
     /// Returns the scalar value stored in this node
-    pub fn data(&self) -> f64 {
-        self.0.borrow().data
+    pub fn data(&self) -> T {
+        self.read().data
     }
 
     /// Sets the scalar value stored in this node
-    pub fn set_data(&self, data: f64) {
-        self.0.borrow_mut().data = data;
+    pub fn set_data(&self, data: T) {
+        self.write().data = data;
     }
 
-    /// Returns the label of this node
+    /// Returns the label of this node.
+    ///
+    /// Nodes created by ops do not store a label unless one is set with
+    /// [`Value::set_label`]. Their name (e.g. `(x1_*_w1`) is instead derived from the
+    /// graph here, so ops don't pay for string formatting that is rarely looked at.
     pub fn label(&self) -> String {
-        self.0.borrow().label.clone()
+        if let Some(ref label) = self.read().label {
+            return label.clone();
+        }
+
+        // Collect the nodes whose labels are needed in topological order, counting how
+        // often each one is read by its parents so that its label can be dropped (or
+        // moved) once the last parent has used it. Labeled nodes and leaves are named
+        // without looking at their inputs, so the walk stops there.
+        let mut visited = HashSet::new();
+        let mut uses: HashMap<usize, usize> = HashMap::new();
+        let mut order = Vec::new();
+        let mut stack = vec![(self.clone(), false)];
+        while let Some((node, expanded)) = stack.pop() {
+            if expanded {
+                order.push(node);
+                continue;
+            }
+            if !visited.insert(node.id()) {
+                continue;
+            }
+            stack.push((node.clone(), true));
+            let internal = node.read();
+            if internal.label.is_none() && internal.op.is_some() {
+                for child in &internal.prev {
+                    *uses.entry(child.id()).or_insert(0) += 1;
+                    if !visited.contains(&child.id()) {
+                        stack.push((child.clone(), false));
+                    }
+                }
+            }
+        }
+
+        let mut labels: HashMap<usize, String> = HashMap::new();
+        for node in &order {
+            let label = node.read().label_with(|child| {
+                let id = child.id();
+                let left = uses.get_mut(&id).expect("every input is counted");
+                *left -= 1;
+                if *left == 0 {
+                    labels.remove(&id).unwrap_or_default()
+                } else {
+                    labels[&id].clone()
+                }
+            });
+            labels.insert(node.id(), label);
+        }
+        labels.remove(&self.id()).unwrap_or_default()
     }
 
-    /// Returns the operation of this node
-    pub fn op(&self) -> String {
-        self.0.borrow().op.clone()
+    /// Returns the label of every node in the graph rooted at this node, keyed by
+    /// node id. The labels are derived in a single pass, so the visualizers, which
+    /// show every node, should build this map once per drawing rather than call
+    /// [`Value::label`] on each node.
+    pub(crate) fn labels(&self) -> HashMap<usize, String> {
+        let mut labels: HashMap<usize, String> = HashMap::new();
+        for node in self.build_topo() {
+            let label = node.read().label_with(|child| labels[&child.id()].clone());
+            labels.insert(node.id(), label);
+        }
+        labels
+    }
+
+    /// Returns the operation that produced this node, or `None` for leaves
+    pub fn op(&self) -> Option<Op<T>> {
+        self.read().op.clone()
     }
 
     /// Returns the gradient of this node
-    pub fn grad(&self) -> f64 {
-        self.0.borrow().grad
+    pub fn grad(&self) -> T {
+        self.read().grad
     }
 
     /// Sets the gradient of this node
-    pub fn set_grad(&self, grad: f64) {
-        self.0.borrow_mut().grad = grad;
+    pub fn set_grad(&self, grad: T) {
+        self.write().grad = grad;
     }
 
     /// Returns the previous nodes of this node
-    pub fn prev(&self) -> Vec<Value> {
-        self.0.borrow().prev.clone()
+    pub fn prev(&self) -> Vec<Value<T>> {
+        self.read().prev.clone()
     }
 
     /// Updates the node's label
     pub fn set_label(&self, label: String) {
-        self.0.borrow_mut().label = label;
+        self.write().label = Some(label);
+    }
+
+    /// Returns whether backprop computes a gradient for this node
+    pub fn requires_grad(&self) -> bool {
+        self.read().requires_grad
+    }
+
+    /// Sets whether backprop computes a gradient for this node.
+    ///
+    /// Backward passes leave the `grad` of a node that does not require one
+    /// untouched and push nothing through it, so freezing a parameter keeps its
+    /// gradient at zero, and freezing an intermediate node detaches everything
+    /// below it. Leaves from [`Value::new`] require a gradient and constants from
+    /// `Value::from` do not; nodes built afterwards only require one if one of their
+    /// inputs does, which lets backprop skip subgraphs made of constants and frozen
+    /// values.
+    pub fn set_requires_grad(&self, requires_grad: bool) {
+        self.write().requires_grad = requires_grad;
     }
 
     /// Initiates backpropagation from this node.
     /// This computes ∂self/∂x for all nodes x in the graph.
+    ///
+    /// Nodes are processed in reverse topological order, so a node only propagates
+    /// its gradient once every node that consumes it has already pushed its gradient
+    /// contribution. This is what makes shared subexpressions (e.g. `a * a`, or a
+    /// weight reused across samples) accumulate the correct gradient.
     pub fn backward(&self) {
-        self.0.borrow_mut().grad = 1.0;
-        let mut visited = HashSet::new();
-        self.backward_internal(&mut visited);
+        self.write().grad = T::one();
+        for node in self.build_topo().iter().rev() {
+            node.backward_step();
+        }
     }
 
-    /// Internal implementation of backprop that includes visualization.
-    /// Uses the chain rule to propagate gradients backward through the graph:
+    /// Computes ∂self/∂x for each x in `wrt`, without modifying the `grad` of any node.
+    ///
+    /// Gradients are accumulated in a map local to this call, so several threads can
+    /// differentiate graphs that share leaves (e.g. model parameters) at the same time.
+    pub fn gradients(&self, wrt: &[Value<T>]) -> Vec<T> {
+        let mut grads: HashMap<usize, T> = HashMap::new();
+        grads.insert(self.id(), T::one());
+
+        for node in self.build_topo().iter().rev() {
+            let Some(&grad) = grads.get(&node.id()) else {
+                continue;
+            };
+            let internal = node.read();
+            if !internal.requires_grad {
+                continue;
+            }
+            if let Some(ref op) = internal.op {
+                let inputs: Vec<T> = internal.prev.iter().map(Value::data).collect();
+                let local_grads = op.local_grads(&inputs, internal.data);
+                for (child, local_grad) in internal.prev.iter().zip(local_grads) {
+                    if child.requires_grad() {
+                        *grads.entry(child.id()).or_insert(T::zero()) += local_grad * grad;
+                    }
+                }
+            }
+        }
+
+        wrt.iter()
+            .map(|x| grads.get(&x.id()).copied().unwrap_or(T::zero()))
+            .collect()
+    }
+
+    /// Resets the gradient of every node in the graph rooted at this node, including
+    /// intermediate ones, so that another backward pass starts from a clean slate.
+    pub fn zero_grad_graph(&self) {
+        for node in self.build_topo() {
+            node.set_grad(T::zero());
+        }
+    }
+
+    /// Applies the chain rule at this single node, pushing its gradient to its inputs.
     /// If y = f(x) and x = g(w), then ∂L/∂w = (∂L/∂y)(∂y/∂x)(∂x/∂w)
-    fn backward_internal(&self, visited: &mut HashSet<usize>) {
-        let ptr = Rc::as_ptr(&self.0) as usize;
-        if visited.insert(ptr) {
-            let internal = self.0.borrow();
-            if let Some(ref backward_fn) = internal.backward_fn {
-                backward_fn(&internal);
+    /// Inputs that do not require a gradient are skipped.
+    fn backward_step(&self) {
+        let internal = self.read();
+        if !internal.requires_grad {
+            return;
+        }
+        if let Some(ref op) = internal.op {
+            let inputs: Vec<T> = internal.prev.iter().map(Value::data).collect();
+            let local_grads = op.local_grads(&inputs, internal.data);
+            debug_assert_eq!(local_grads.len(), inputs.len());
+            for (child, local_grad) in internal.prev.iter().zip(local_grads) {
+                let mut child = child.write();
+                if child.requires_grad {
+                    child.grad += local_grad * internal.grad;
+                }
             }
+        }
+    }
 
-            // Clone prev to avoid borrow issues
-            let prev = internal.prev.clone();
-            drop(internal);
+    /// Runs backprop while recording the gradient computation itself in the graph.
+    ///
+    /// Instead of accumulating plain numbers into `grad`, every gradient ∂self/∂x is
+    /// built as a new Value node from the same ops as the forward pass, so it can be
+    /// differentiated again (Hessian-vector products, gradient penalties, Newton steps).
+    /// The gradients are returned rather than stored on the nodes, since a node holding
+    /// its own gradient (e.g. 2x for x²) would form a reference cycle; the plain `grad`
+    /// of every node is left untouched.
+    pub fn backward_create_graph(&self) -> Gradients<T> {
+        let mut grads: HashMap<usize, Value<T>> = HashMap::new();
+        grads.insert(self.id(), Value::from(T::one()));
 
-            for child in prev {
-                child.backward_internal(visited);
+        for node in self.build_topo().iter().rev() {
+            let Some(grad) = grads.get(&node.id()).cloned() else {
+                continue;
+            };
+            let internal = node.read();
+            if !internal.requires_grad {
+                continue;
+            }
+            if let Some(ref op) = internal.op {
+                let local_grads = op.local_grads_graph(&internal.prev, node);
+                debug_assert_eq!(local_grads.len(), internal.prev.len());
+                for (child, local_grad) in internal.prev.iter().zip(local_grads) {
+                    if !child.requires_grad() {
+                        continue;
+                    }
+                    let contribution = local_grad * &grad;
+                    let total = match grads.remove(&child.id()) {
+                        Some(existing) => existing + contribution,
+                        None => contribution,
+                    };
+                    grads.insert(child.id(), total);
+                }
             }
         }
+
+        Gradients(grads)
     }
 
-    /// Implements binary operations (+, -, *, /) between Values.
-    /// Each operation stores its inputs and a closure for computing gradients.
-    fn binary_op(left: &Value, right: &Value, op: &str) -> Value {
-        let data = match op {
-            "+" => left.data() + right.data(),
-            "*" => left.data() * right.data(),
-            "-" => left.data() - right.data(),
-            "/" => left.data() / right.data(),
-            _ => unreachable!(),
-        };
+    /// Creates the node produced by applying `op` to `children`.
+    /// The op computes the output data here and the local gradients during backprop.
+    ///
+    /// Inside a [`no_grad`] scope only the output data is computed: the node is a
+    /// plain leaf with no inputs or op, so no graph is recorded. Otherwise the node
+    /// requires a gradient if any of its inputs does.
+    fn apply(op: Op<T>, children: &[&Value<T>]) -> Value<T> {
+        let inputs: Vec<T> = children.iter().map(|c| c.data()).collect();
+        let data = op.forward(&inputs);
+        if !is_grad_enabled() {
+            return Value::node(data, Vec::new(), None, None, false);
+        }
+        let requires_grad = children.iter().any(|c| c.requires_grad());
+        let children = children.iter().map(|&c| c.clone()).collect();
+        Value::node(data, children, Some(op), None, requires_grad)
+    }
 
-        let out = Value::new(
-            data,
-            Some(vec![left.clone(), right.clone()]),
-            format!(
-                "({}_{}_{}",
-                left.0.borrow().label,
-                op,
-                right.0.borrow().label
-            ),
-            Some(op.to_string()),
+    /// Applies a user-defined differentiable function to the given inputs.
+    /// The same handle can be shared between every node that uses the operation.
+    pub fn custom(op: CustomOpRef<T>, inputs: &[Value<T>]) -> Value<T> {
+        Value::apply(Op::Custom(op), &inputs.iter().collect::<Vec<_>>())
+    }
+
+    /// Sums `xs` into a single node, whose backward pushes the gradient to every input
+    /// at once instead of going through a chain of `n - 1` additions.
+    pub fn sum(xs: &[Value<T>]) -> Value<T> {
+        if xs.is_empty() {
+            return Value::from(T::zero());
+        }
+        Value::apply(Op::Sum, &xs.iter().collect::<Vec<_>>())
+    }
+
+    /// Computes the dot product Σ aᵢbᵢ as a single node with a fused backward.
+    pub fn dot(a: &[Value<T>], b: &[Value<T>]) -> Value<T> {
+        assert_eq!(
+            a.len(),
+            b.len(),
+            "dot product of vectors of different lengths"
         );
+        if a.is_empty() {
+            return Value::from(T::zero());
+        }
+        Value::apply(Op::Dot, &a.iter().chain(b).collect::<Vec<_>>())
+    }
 
-        // Set backward functions with proper closure captures
-        let backward_fn: Option<BackwardFn> = match op {
-            "+" => Some(Box::new(move |out| {
-                let g = out.grad;
-                out.prev[0].0.borrow_mut().grad += g;
-                out.prev[1].0.borrow_mut().grad += g;
-            })),
-            "*" => Some(Box::new(move |out| {
-                let g = out.grad;
-                out.prev[0].0.borrow_mut().grad += out.prev[1].data() * g;
-                out.prev[1].0.borrow_mut().grad += out.prev[0].data() * g;
-            })),
-            "-" => Some(Box::new(move |out| {
-                let g = out.grad;
-                out.prev[0].0.borrow_mut().grad += g;
-                out.prev[1].0.borrow_mut().grad -= g;
-            })),
-            "/" => Some(Box::new(move |out| {
-                let g = out.grad;
-                out.prev[0].0.borrow_mut().grad += g * (1.0 / out.prev[1].data());
-                out.prev[1].0.borrow_mut().grad +=
-                    out.grad * (-out.prev[0].data() / out.prev[1].data().powi(2));
-            })),
-            _ => None,
-        };
+    /// Computes ln Σ e^xᵢ as a single node. The forward pass subtracts the maximum
+    /// before exponentiating, so large inputs do not overflow, and the backward pass
+    /// pushes softmax(x) to all inputs at once.
+    pub fn logsumexp(xs: &[Value<T>]) -> Value<T> {
+        if xs.is_empty() {
+            return Value::from(T::from_f64(f64::NEG_INFINITY));
+        }
+        Value::apply(Op::LogSumExp, &xs.iter().collect::<Vec<_>>())
+    }
+
+    /// Computes log softmax(x)ᵢ = xᵢ - logsumexp(x). Every output shares the same
+    /// logsumexp node, so backprop through all of them stays linear in `xs.len()`.
+    pub fn log_softmax(xs: &[Value<T>]) -> Vec<Value<T>> {
+        let lse = Value::logsumexp(xs);
+        xs.iter().map(|x| x - &lse).collect()
+    }
 
-        out.0.borrow_mut().backward_fn = backward_fn;
-        out
+    /// Computes softmax(x)ᵢ = e^xᵢ / Σ e^xⱼ as e^(xᵢ - logsumexp(x)), which never
+    /// exponentiates a positive number. Works directly on the output of `MLP::forward`.
+    pub fn softmax(xs: &[Value<T>]) -> Vec<Value<T>> {
+        Value::log_softmax(xs).iter().map(Value::exp).collect()
+    }
+
+    /// Implements binary operations (+, -, *, /) between Values.
+    fn binary_op(left: &Value<T>, right: &Value<T>, op: Op<T>) -> Value<T> {
+        Value::apply(op, &[left, right])
     }
 
     /// Implements hyperbolic tangent activation function.
     /// tanh(x) = (e^x - e^-x)/(e^x + e^-x)
-    pub fn tanh(&self) -> Value {
-        let x = self.data();
-        let exp_pos = x.exp();
-        let exp_neg = (-x).exp();
-        let t = (exp_pos - exp_neg) / (exp_pos + exp_neg);
-        let out = Value::new(
-            t,
-            Some(vec![self.clone()]),
-            format!("tanh({})", self.0.borrow().label),
-            Some("tanh".to_string()),
-        );
-
-        out.0.borrow_mut().backward_fn = Some(Box::new(move |out| {
-            // For tanh(x), the derivative is:
-            // ∂tanh(x)/∂x = 1 - tanh²(x)
-            let t = out.data; // t is already the tanh result
-            out.prev[0].0.borrow_mut().grad += (1.0 - t * t) * out.grad;
-        }));
-        out
+    pub fn tanh(&self) -> Value<T> {
+        Value::apply(Op::Tanh, &[self])
     }
 
     /// Builds a topologically sorted list of all nodes in the graph.
     /// This ensures that when we process nodes, all dependencies are handled first.
-    pub fn build_topo(&self) -> Vec<Value> {
+    ///
+    /// The walk uses an explicit stack rather than recursion so that very deep graphs
+    /// (long unrolled chains, sums over many terms) cannot overflow the call stack.
+    pub fn build_topo(&self) -> Vec<Value<T>> {
         let mut topo = Vec::new();
         let mut visited = HashSet::new();
+        // Each entry is a node plus whether its dependencies have already been pushed.
+        let mut stack = vec![(self.clone(), false)];
 
-        fn build_topo_recursive(v: &Value, topo: &mut Vec<Value>, visited: &mut HashSet<usize>) {
-            let ptr = Rc::as_ptr(&v.0) as usize;
-            if !visited.contains(&ptr) {
-                visited.insert(ptr);
+        while let Some((v, expanded)) = stack.pop() {
+            if expanded {
+                // All dependencies have been emitted, so this node can follow them
+                topo.push(v);
+                continue;
+            }
+            if !visited.insert(v.id()) {
+                continue;
+            }
 
-                // Visit all dependencies first
-                for child in &v.0.borrow().prev {
-                    build_topo_recursive(child, topo, visited);
+            stack.push((v.clone(), true));
+            // Push in reverse so dependencies are visited in their original order
+            for child in v.read().prev.iter().rev() {
+                if !visited.contains(&child.id()) {
+                    stack.push((child.clone(), false));
                 }
-
-                // Then add this node
-                topo.push(v.clone());
             }
         }
 
-        build_topo_recursive(self, &mut topo, &mut visited);
         topo
     }
 
     /// Implements power function x^n.
-    pub fn pow(&self, exponent: f64) -> Value {
-        let out = Value::new(
-            self.data().powf(exponent),
-            Some(vec![self.clone()]),
-            format!("{}^{}", self.0.borrow().label, exponent),
-            Some("pow".to_string()),
-        );
-
-        out.0.borrow_mut().backward_fn = Some(Box::new(move |out| {
-            // Power rule: ∂(x^n)/∂x = n * x^(n-1)
-            out.prev[0].0.borrow_mut().grad +=
-                exponent * out.prev[0].data().powf(exponent - 1.0) * out.grad;
-        }));
-        out
+    pub fn pow(&self, exponent: T) -> Value<T> {
+        Value::apply(Op::Pow(exponent), &[self])
     }
 
     /// Implements ReLU (Rectified Linear Unit) activation function.
     /// ReLU(x) = max(0, x)
-    pub fn relu(&self) -> Value {
-        let out = Value::new(
-            if self.data() > 0.0 { self.data() } else { 0.0 },
-            Some(vec![self.clone()]),
-            format!("relu({})", self.0.borrow().label),
-            Some("relu".to_string()),
-        );
+    pub fn relu(&self) -> Value<T> {
+        Value::apply(Op::Relu, &[self])
+    }
 
-        out.0.borrow_mut().backward_fn = Some(Box::new(move |out| {
-            // ReLU derivative:
-            // ∂ReLU(x)/∂x = 1 if x > 0, else 0
-            out.prev[0].0.borrow_mut().grad += if out.data > 0.0 { out.grad } else { 0.0 };
-        }));
-        out
+    /// Implements the exponential function e^x.
+    pub fn exp(&self) -> Value<T> {
+        Value::apply(Op::Exp, &[self])
     }
 
-    /// Initiates backpropagation from this node with visualization.
-    /// This computes ∂self/∂x for all nodes x in the graph.
-    pub fn backward_with_viz(&self, viz: &mut BackpropViz) {
-        self.0.borrow_mut().grad = 1.0;
-        let mut visited = HashSet::new();
-        self.backward_internal_with_viz(&mut visited, viz);
+    /// Implements the natural logarithm ln(x).
+    pub fn ln(&self) -> Value<T> {
+        Value::apply(Op::Ln, &[self])
     }
 
-    /// Internal implementation of backprop that includes visualization.
-    /// Uses the chain rule to propagate gradients backward through the graph:
-    /// If y = f(x) and x = g(w), then ∂L/∂w = (∂L/∂y)(∂y/∂x)(∂x/∂w)
-    fn backward_internal_with_viz(&self, visited: &mut HashSet<usize>, viz: &mut BackpropViz) {
-        let ptr = Rc::as_ptr(&self.0) as usize;
-        if visited.insert(ptr) {
+    /// Implements the base-2 logarithm log2(x).
+    pub fn log2(&self) -> Value<T> {
+        Value::apply(Op::Log2, &[self])
+    }
+
+    /// Implements the logistic sigmoid activation function.
+    /// σ(x) = 1/(1 + e^-x)
+    pub fn sigmoid(&self) -> Value<T> {
+        Value::apply(Op::Sigmoid, &[self])
+    }
+
+    /// Implements the softplus activation function.
+    /// softplus(x) = ln(1 + e^x)
+    pub fn softplus(&self) -> Value<T> {
+        Value::apply(Op::Softplus, &[self])
+    }
+
+    /// Implements the GELU activation function using the tanh approximation.
+    /// GELU(x) = 0.5x(1 + tanh(√(2/π)(x + 0.044715x³)))
+    pub fn gelu(&self) -> Value<T> {
+        Value::apply(Op::Gelu, &[self])
+    }
+
+    /// Implements the SiLU (swish) activation function.
+    /// SiLU(x) = x·σ(x)
+    pub fn silu(&self) -> Value<T> {
+        Value::apply(Op::Silu, &[self])
+    }
+
+    /// Implements leaky ReLU activation function.
+    /// LeakyReLU(x) = x if x > 0, else alpha·x
+    pub fn leaky_relu(&self, alpha: T) -> Value<T> {
+        Value::apply(Op::LeakyRelu(alpha), &[self])
+    }
+
+    /// Implements ELU activation function with alpha = 1.
+    /// ELU(x) = x if x > 0, else e^x - 1
+    pub fn elu(&self) -> Value<T> {
+        Value::apply(Op::Elu, &[self])
+    }
+
+    /// Implements the absolute value |x|.
+    pub fn abs(&self) -> Value<T> {
+        Value::apply(Op::Abs, &[self])
+    }
+
+    /// Implements the square root √x.
+    pub fn sqrt(&self) -> Value<T> {
+        Value::apply(Op::Sqrt, &[self])
+    }
+
+    /// Implements the sine function.
+    pub fn sin(&self) -> Value<T> {
+        Value::apply(Op::Sin, &[self])
+    }
+
+    /// Implements the cosine function.
+    pub fn cos(&self) -> Value<T> {
+        Value::apply(Op::Cos, &[self])
+    }
+
+    /// Initiates backpropagation from this node with visualization.
+    /// This computes ∂self/∂x for all nodes x in the graph, visiting nodes in the
+    /// same reverse topological order as [`Value::backward`].
+    pub fn backward_with_viz(&self, viz: &mut BackpropViz) {
+        self.write().grad = T::one();
+        let labels = self.labels();
+        for node in self.build_topo().iter().rev() {
+            let ptr = node.id();
             viz.active_nodes.insert(ptr);
             let desc = format!(
                 "Computing gradient for node '{}'\n\
                 Current value: {:.4}\n\
                 Current gradient: {:.4}\n\
                 Operation: {}",
-                self.label(),
-                self.data(),
-                self.grad(),
-                self.op()
+                labels[&ptr],
+                node.data(),
+                node.grad(),
+                node.op().map(|op| op.to_string()).unwrap_or_default()
             );
             viz.draw_step(self, &desc);
 
-            let internal = self.0.borrow();
-            if let Some(ref backward_fn) = internal.backward_fn {
-                backward_fn(&internal);
-            }
-
-            let prev = internal.prev.clone();
-            drop(internal);
+            node.backward_step();
 
             viz.completed_nodes.insert(ptr);
             viz.active_nodes.remove(&ptr);
+        }
+    }
+}
+
+thread_local! {
+    /// Whether ops on this thread record the computation graph.
+    static GRAD_ENABLED: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Returns whether ops on the current thread record the graph for backprop.
+pub fn is_grad_enabled() -> bool {
+    GRAD_ENABLED.with(Cell::get)
+}
+
+/// Disables graph construction on the current thread until the returned guard is
+/// dropped. Ops then only compute their output, which is much cheaper when
+/// gradients are not needed, e.g. when evaluating a model on a test set.
+pub fn no_grad() -> NoGradGuard {
+    let prev = GRAD_ENABLED.with(|enabled| enabled.replace(false));
+    NoGradGuard { prev }
+}
+
+/// Restores the previous graph recording mode when dropped, see [`no_grad`].
+pub struct NoGradGuard {
+    prev: bool,
+}
+
+impl Drop for NoGradGuard {
+    fn drop(&mut self) {
+        GRAD_ENABLED.with(|enabled| enabled.set(self.prev));
+    }
+}
+
+/// Gradients produced by [`Value::backward_create_graph`], each one a differentiable node.
+pub struct Gradients<T: Float = f64>(HashMap<usize, Value<T>>);
+
+impl<T: Float> Gradients<T> {
+    /// Returns ∂output/∂x, or `None` if the output does not depend on `x`
+    pub fn get(&self, x: &Value<T>) -> Option<Value<T>> {
+        self.0.get(&x.id()).cloned()
+    }
+
+    /// Returns ∂output/∂x for each x in `xs`, using a constant 0 where the output
+    /// does not depend on x
+    pub fn wrt(&self, xs: &[Value<T>]) -> Vec<Value<T>> {
+        xs.iter()
+            .map(|x| self.get(x).unwrap_or_else(|| Value::from(T::zero())))
+            .collect()
+    }
+}
 
-            for child in prev {
-                child.backward_internal_with_viz(visited, viz);
+impl<T: Float> ValueInternal<T> {
+    /// Names this node, looking up the labels of its inputs with `input_label` only
+    /// when the name has to be derived from them.
+    fn label_with(&self, input_label: impl FnMut(&Value<T>) -> String) -> String {
+        match (&self.label, &self.op) {
+            (Some(label), _) => label.clone(),
+            // Unlabeled leaves, such as constants, are named after their data
+            (None, None) => self.data.to_string(),
+            (None, Some(op)) => {
+                let inputs: Vec<String> = self.prev.iter().map(input_label).collect();
+                op.label(&inputs)
             }
         }
     }
 }
 
-// Implement ops traits
-impl Add for &Value {
-    type Output = Value;
-    fn add(self, rhs: &Value) -> Value {
-        Value::binary_op(self, rhs, "+")
+/// Access to the shared node behind a Value, for the default single-threaded build.
+#[cfg(not(feature = "sync"))]
+impl<T: Float> Value<T> {
+    fn wrap(internal: ValueInternal<T>) -> Self {
+        Value(Rc::new(RefCell::new(internal)))
+    }
+
+    fn read(&self) -> Ref<'_, ValueInternal<T>> {
+        self.0.borrow()
+    }
+
+    fn write(&self) -> RefMut<'_, ValueInternal<T>> {
+        self.0.borrow_mut()
+    }
+
+    /// Returns an identifier for this node that is shared by all of its clones.
+    pub(crate) fn id(&self) -> usize {
+        Rc::as_ptr(&self.0) as usize
+    }
+
+    /// Takes the node out if this is its last reference
+    fn into_unique(self) -> Option<ValueInternal<T>> {
+        Rc::try_unwrap(self.0).ok().map(RefCell::into_inner)
     }
 }
 
-impl Sub for &Value {
-    type Output = Value;
-    fn sub(self, rhs: &Value) -> Value {
-        Value::binary_op(self, rhs, "-")
+/// Access to the shared node behind a Value, for the thread-safe `sync` build.
+/// A panic while a lock is held cannot leave a node half-updated, so poisoned
+/// locks are simply recovered.
+#[cfg(feature = "sync")]
+impl<T: Float> Value<T> {
+    fn wrap(internal: ValueInternal<T>) -> Self {
+        Value(Arc::new(RwLock::new(internal)))
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, ValueInternal<T>> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, ValueInternal<T>> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns an identifier for this node that is shared by all of its clones.
+    pub(crate) fn id(&self) -> usize {
+        Arc::as_ptr(&self.0) as usize
+    }
+
+    /// Takes the node out if this is its last reference
+    fn into_unique(self) -> Option<ValueInternal<T>> {
+        Arc::try_unwrap(self.0)
+            .ok()
+            .map(|lock| lock.into_inner().unwrap_or_else(PoisonError::into_inner))
     }
 }
 
-impl Mul for &Value {
-    type Output = Value;
-    fn mul(self, rhs: &Value) -> Value {
-        Value::binary_op(self, rhs, "*")
+/// Tears down the graph iteratively. The default drop glue would recurse once per
+/// node along a chain, overflowing the stack when a long graph is released.
+impl<T: Float> Drop for ValueInternal<T> {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.prev);
+        while let Some(v) = stack.pop() {
+            // Only take over the children of nodes nobody else references; shared
+            // nodes stay alive and will be torn down by their last owner.
+            if let Some(mut internal) = v.into_unique() {
+                stack.append(&mut internal.prev);
+            }
+        }
     }
 }
 
-impl Div for &Value {
-    type Output = Value;
-    fn div(self, rhs: &Value) -> Value {
-        Value::binary_op(self, rhs, "/")
+/// Wraps a constant as a leaf node, so scalars can be mixed freely into expressions.
+/// Constants do not require a gradient (see [`Value::set_requires_grad`]); use
+/// [`Value::new`] for a variable to differentiate with respect to.
+impl<T: Float> From<T> for Value<T> {
+    fn from(data: T) -> Self {
+        Value::node(data, Vec::new(), None, None, false)
     }
 }
 
-impl Debug for Value {
+/// Implements a binary operator for every combination of `Value`, `&Value` and scalar
+/// operands (with at least one side being a Value), plus the matching compound
+/// assignment operator. All variants funnel into `Value::binary_op`.
+///
+/// A scalar on the right can be any [`Float`], while a scalar on the left is only
+/// supported for the listed primitive types, as the orphan rule forbids implementing
+/// `Add<Value<T>>` for an arbitrary `T`.
+macro_rules! impl_binary_op {
+    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident, $op:expr; $($float:ty),*) => {
+        impl<T: Float> $trait<&Value<T>> for &Value<T> {
+            type Output = Value<T>;
+            fn $method(self, rhs: &Value<T>) -> Value<T> {
+                Value::binary_op(self, rhs, $op)
+            }
+        }
+
+        impl<T: Float> $trait<Value<T>> for &Value<T> {
+            type Output = Value<T>;
+            fn $method(self, rhs: Value<T>) -> Value<T> {
+                Value::binary_op(self, &rhs, $op)
+            }
+        }
+
+        impl<T: Float> $trait<&Value<T>> for Value<T> {
+            type Output = Value<T>;
+            fn $method(self, rhs: &Value<T>) -> Value<T> {
+                Value::binary_op(&self, rhs, $op)
+            }
+        }
+
+        impl<T: Float> $trait<Value<T>> for Value<T> {
+            type Output = Value<T>;
+            fn $method(self, rhs: Value<T>) -> Value<T> {
+                Value::binary_op(&self, &rhs, $op)
+            }
+        }
+
+        impl<T: Float> $trait<T> for &Value<T> {
+            type Output = Value<T>;
+            fn $method(self, rhs: T) -> Value<T> {
+                Value::binary_op(self, &Value::from(rhs), $op)
+            }
+        }
+
+        impl<T: Float> $trait<T> for Value<T> {
+            type Output = Value<T>;
+            fn $method(self, rhs: T) -> Value<T> {
+                Value::binary_op(&self, &Value::from(rhs), $op)
+            }
+        }
+
+        $(
+            impl $trait<&Value<$float>> for $float {
+                type Output = Value<$float>;
+                fn $method(self, rhs: &Value<$float>) -> Value<$float> {
+                    Value::binary_op(&Value::from(self), rhs, $op)
+                }
+            }
+
+            impl $trait<Value<$float>> for $float {
+                type Output = Value<$float>;
+                fn $method(self, rhs: Value<$float>) -> Value<$float> {
+                    Value::binary_op(&Value::from(self), &rhs, $op)
+                }
+            }
+        )*
+
+        impl<T: Float> $assign_trait<&Value<T>> for Value<T> {
+            fn $assign_method(&mut self, rhs: &Value<T>) {
+                *self = Value::binary_op(self, rhs, $op);
+            }
+        }
+
+        impl<T: Float> $assign_trait<Value<T>> for Value<T> {
+            fn $assign_method(&mut self, rhs: Value<T>) {
+                *self = Value::binary_op(self, &rhs, $op);
+            }
+        }
+
+        impl<T: Float> $assign_trait<T> for Value<T> {
+            fn $assign_method(&mut self, rhs: T) {
+                *self = Value::binary_op(self, &Value::from(rhs), $op);
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add, AddAssign, add_assign, Op::Add; f32, f64);
+impl_binary_op!(Sub, sub, SubAssign, sub_assign, Op::Sub; f32, f64);
+impl_binary_op!(Mul, mul, MulAssign, mul_assign, Op::Mul; f32, f64);
+impl_binary_op!(Div, div, DivAssign, div_assign, Op::Div; f32, f64);
+
+impl<T: Float> Neg for &Value<T> {
+    type Output = Value<T>;
+    fn neg(self) -> Value<T> {
+        Value::apply(Op::Neg, &[self])
+    }
+}
+
+impl<T: Float> Neg for Value<T> {
+    type Output = Value<T>;
+    fn neg(self) -> Value<T> {
+        -&self
+    }
+}
+
+impl<T: Float> Sum for Value<T> {
+    fn sum<I: Iterator<Item = Value<T>>>(iter: I) -> Value<T> {
+        iter.fold(Value::from(T::zero()), |acc, v| acc + v)
+    }
+}
+
+impl<'a, T: Float> Sum<&'a Value<T>> for Value<T> {
+    fn sum<I: Iterator<Item = &'a Value<T>>>(iter: I) -> Value<T> {
+        iter.fold(Value::from(T::zero()), |acc, v| acc + v)
+    }
+}
+
+impl<T: Float> Product for Value<T> {
+    fn product<I: Iterator<Item = Value<T>>>(iter: I) -> Value<T> {
+        iter.fold(Value::from(T::one()), |acc, v| acc * v)
+    }
+}
+
+impl<'a, T: Float> Product<&'a Value<T>> for Value<T> {
+    fn product<I: Iterator<Item = &'a Value<T>>>(iter: I) -> Value<T> {
+        iter.fold(Value::from(T::one()), |acc, v| acc * v)
+    }
+}
+
+impl<T: Float> Debug for Value<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Value(data: {}, grad: {})", self.data(), self.grad())
     }
 }
 
-impl Display for Value {
+impl<T: Float> Display for Value<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Value(data: {}, grad: {})", self.data(), self.grad())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradcheck::gradcheck;
+
+    fn leaf(data: f64, label: &str) -> Value {
+        Value::new(data, None, label.to_string(), None)
+    }
+
+    #[test]
+    fn labels_match_label() {
+        let (a, b) = (leaf(2.0, "a"), leaf(-3.0, "b"));
+        let c = &a * &b;
+        let d = (&c + &c * 2.0).tanh();
+        let named = &d * &a;
+        named.set_label("named".to_string());
+        let out = &named + &(&d - 1.0);
+
+        assert_eq!(d.label(), "tanh(((a_*_b_+_((a_*_b_*_2)");
+        let labels = out.labels();
+        for node in out.build_topo() {
+            assert_eq!(labels[&node.id()], node.label());
+        }
+        assert_eq!(labels.len(), out.build_topo().len());
+    }
+
+    #[test]
+    fn deep_chain_does_not_overflow_stack() {
+        let a = leaf(0.0, "a");
+        let mut out = a.clone();
+        let n = 100_000;
+        for _ in 0..n {
+            out = &out + 1.0;
+        }
+        out.backward();
+        assert_eq!((out.data(), a.grad()), (100_000.0, 1.0));
+        assert_eq!(out.label(), "(".repeat(n) + "a" + &"_+_1".repeat(n));
+        // Dropping the last handle frees the whole chain
+        drop(out);
+    }
+
+    #[test]
+    fn no_grad_records_no_graph() {
+        let (a, b) = (leaf(2.0, "a"), leaf(-3.0, "b"));
+        let out = {
+            let _guard = no_grad();
+            (&a * &b).tanh() + 1.0
+        };
+        assert_eq!(out.data(), (-6f64).tanh() + 1.0);
+        assert!(out.prev().is_empty() && out.op().is_none());
+        assert!(!out.requires_grad());
+
+        // Graphs are recorded again once the guard is gone
+        assert_eq!((&a * &b).prev().len(), 2);
+    }
+
+    #[test]
+    fn nested_no_grad_restores_previous_mode() {
+        assert!(is_grad_enabled());
+        let outer = no_grad();
+        {
+            let _inner = no_grad();
+            assert!(!is_grad_enabled());
+        }
+        // Dropping the inner guard returns to the outer guard's mode
+        assert!(!is_grad_enabled());
+        drop(outer);
+        assert!(is_grad_enabled());
+    }
+
+    #[test]
+    fn gradients_match_backward() {
+        let (a, b) = (leaf(0.7, "a"), leaf(-1.2, "b"));
+        let c = &a * &b;
+        let d = &c + &(&c * &a);
+        let out = (&d * &c).tanh() + &a;
+
+        let grads = out.gradients(&[a.clone(), b.clone(), c.clone()]);
+        assert_eq!((a.grad(), b.grad(), c.grad()), (0.0, 0.0, 0.0));
+        out.backward();
+        assert_eq!(grads, vec![a.grad(), b.grad(), c.grad()]);
+    }
+
+    /// Checks the gradient of a unary op at each point against finite differences.
+    /// Points are kept away from kinks, where the two sides have different slopes.
+    fn check_unary(op: impl Fn(&Value) -> Value, points: &[f64]) {
+        for &x in points {
+            let report = gradcheck(|x| op(&x[0]), &[leaf(x, "x")]);
+            assert!(report.is_ok(), "at x = {}: {}", x, report);
+        }
+    }
+
+    #[test]
+    fn backward_square_of_shared_node() {
+        // d(a²)/da = 2a
+        let a = leaf(3.0, "a");
+        let b = &a * &a;
+        b.backward();
+        assert_eq!(b.data(), 9.0);
+        assert_eq!(a.grad(), 6.0);
+    }
+
+    #[test]
+    fn backward_diamond() {
+        // d = ab + ab·a = ab + a²b, so ∂d/∂a = b + 2ab and ∂d/∂b = a + a²
+        let (a, b) = (leaf(2.0, "a"), leaf(-3.0, "b"));
+        let c = &a * &b;
+        let d = &c + &(&c * &a);
+        d.backward();
+        assert_eq!(a.grad(), -3.0 + 2.0 * 2.0 * -3.0);
+        assert_eq!(b.grad(), 2.0 + 2.0 * 2.0);
+        assert_eq!(c.grad(), 1.0 + 2.0);
+    }
+
+    #[test]
+    fn backward_weight_reused_across_samples() {
+        // L = (w·x₁ - y₁)² + (w·x₂ - y₂)², so ∂L/∂w = Σ 2(w·xᵢ - yᵢ)·xᵢ
+        let w = leaf(0.5, "w");
+        let samples = [(2.0, 1.0), (-1.0, 3.0)];
+        let loss: Value = samples.iter().map(|&(x, y)| (&w * x - y).pow(2.0)).sum();
+        loss.backward();
+        let expected: f64 = samples.iter().map(|&(x, y)| 2.0 * (0.5 * x - y) * x).sum();
+        assert_eq!(w.grad(), expected);
+    }
+
+    #[test]
+    fn gradcheck_neg() {
+        check_unary(|x| -x, &[-0.8, 0.8]);
+    }
+
+    #[test]
+    fn gradcheck_exp() {
+        check_unary(Value::exp, &[-1.5, 0.9]);
+    }
+
+    #[test]
+    fn gradcheck_ln() {
+        check_unary(Value::ln, &[0.3, 2.5]);
+    }
+
+    #[test]
+    fn gradcheck_log2() {
+        check_unary(Value::log2, &[0.3, 2.5]);
+    }
+
+    #[test]
+    fn gradcheck_sigmoid() {
+        check_unary(Value::sigmoid, &[-1.2, 0.0, 2.1]);
+    }
+
+    #[test]
+    fn gradcheck_softplus() {
+        check_unary(Value::softplus, &[-1.2, 0.0, 2.1]);
+    }
+
+    #[test]
+    fn gradcheck_gelu() {
+        check_unary(Value::gelu, &[-0.6, 0.0, 1.4]);
+    }
+
+    #[test]
+    fn gradcheck_silu() {
+        check_unary(Value::silu, &[-0.6, 0.0, 1.4]);
+    }
+
+    #[test]
+    fn gradcheck_leaky_relu() {
+        check_unary(|x| x.leaky_relu(0.01), &[-0.4, 0.4]);
+    }
+
+    #[test]
+    fn gradcheck_elu() {
+        check_unary(Value::elu, &[-0.4, 0.4]);
+    }
+
+    #[test]
+    fn gradcheck_abs() {
+        check_unary(Value::abs, &[-0.6, 0.6]);
+    }
+
+    #[test]
+    fn gradcheck_sqrt() {
+        check_unary(Value::sqrt, &[0.2, 2.3]);
+    }
+
+    #[test]
+    fn gradcheck_sin() {
+        check_unary(Value::sin, &[-0.7, 2.3]);
+    }
+
+    #[test]
+    fn gradcheck_cos() {
+        check_unary(Value::cos, &[-0.7, 2.3]);
+    }
+
+    #[test]
+    fn constants_do_not_require_grad() {
+        let (x, c) = (leaf(1.5, "x"), Value::from(2.0));
+        let scaled = &c * 3.0;
+        let out = &x * &scaled;
+        assert!(x.requires_grad() && out.requires_grad());
+        assert!(!c.requires_grad() && !scaled.requires_grad());
+
+        out.backward();
+        assert_eq!(x.grad(), 6.0);
+        assert_eq!((c.grad(), scaled.grad()), (0.0, 0.0));
+    }
+}
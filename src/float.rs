@@ -0,0 +1,164 @@
+//! The number types a [`Value`](crate::Value) can hold.
+//! `f64` is the default, `f32` halves the memory of a model, and any other type that
+//! implements [`Float`] (an interval type, an exact rational, ...) can be plugged in.
+
+use std::{
+    fmt::{Debug, Display},
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub},
+};
+
+/// A real number type the autograd engine can compute with.
+///
+/// Only the arithmetic operators and the conversions to and from `f64` must be
+/// provided. The elementary functions default to evaluating in `f64` and converting
+/// back, so a custom type only overrides those it can compute more faithfully.
+pub trait Float:
+    Copy
+    + PartialOrd
+    + Debug
+    + Display
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+{
+    /// Converts a constant, such as a literal coefficient inside an op, to this type
+    fn from_f64(x: f64) -> Self;
+
+    /// Converts to `f64`, e.g. for reporting a loss
+    fn to_f64(self) -> f64;
+
+    fn zero() -> Self {
+        Self::from_f64(0.0)
+    }
+
+    fn one() -> Self {
+        Self::from_f64(1.0)
+    }
+
+    fn abs(self) -> Self {
+        if self < Self::zero() {
+            -self
+        } else {
+            self
+        }
+    }
+
+    fn max(self, other: Self) -> Self {
+        if other > self {
+            other
+        } else {
+            self
+        }
+    }
+
+    fn powf(self, exponent: Self) -> Self {
+        Self::from_f64(self.to_f64().powf(exponent.to_f64()))
+    }
+
+    fn exp(self) -> Self {
+        Self::from_f64(self.to_f64().exp())
+    }
+
+    /// e^x - 1, accurate for x close to zero
+    fn exp_m1(self) -> Self {
+        Self::from_f64(self.to_f64().exp_m1())
+    }
+
+    fn ln(self) -> Self {
+        Self::from_f64(self.to_f64().ln())
+    }
+
+    /// ln(1 + x), accurate for x close to zero
+    fn ln_1p(self) -> Self {
+        Self::from_f64(self.to_f64().ln_1p())
+    }
+
+    fn log2(self) -> Self {
+        Self::from_f64(self.to_f64().log2())
+    }
+
+    fn tanh(self) -> Self {
+        Self::from_f64(self.to_f64().tanh())
+    }
+
+    fn sqrt(self) -> Self {
+        Self::from_f64(self.to_f64().sqrt())
+    }
+
+    fn sin(self) -> Self {
+        Self::from_f64(self.to_f64().sin())
+    }
+
+    fn cos(self) -> Self {
+        Self::from_f64(self.to_f64().cos())
+    }
+}
+
+/// Implements [`Float`] for a primitive float type with its native functions.
+macro_rules! impl_float {
+    ($t:ty) => {
+        impl Float for $t {
+            fn from_f64(x: f64) -> Self {
+                x as $t
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn abs(self) -> Self {
+                <$t>::abs(self)
+            }
+
+            fn max(self, other: Self) -> Self {
+                <$t>::max(self, other)
+            }
+
+            fn powf(self, exponent: Self) -> Self {
+                <$t>::powf(self, exponent)
+            }
+
+            fn exp(self) -> Self {
+                <$t>::exp(self)
+            }
+
+            fn exp_m1(self) -> Self {
+                <$t>::exp_m1(self)
+            }
+
+            fn ln(self) -> Self {
+                <$t>::ln(self)
+            }
+
+            fn ln_1p(self) -> Self {
+                <$t>::ln_1p(self)
+            }
+
+            fn log2(self) -> Self {
+                <$t>::log2(self)
+            }
+
+            fn tanh(self) -> Self {
+                <$t>::tanh(self)
+            }
+
+            fn sqrt(self) -> Self {
+                <$t>::sqrt(self)
+            }
+
+            fn sin(self) -> Self {
+                <$t>::sin(self)
+            }
+
+            fn cos(self) -> Self {
+                <$t>::cos(self)
+            }
+        }
+    };
+}
+
+impl_float!(f32);
+impl_float!(f64);
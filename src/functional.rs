@@ -0,0 +1,84 @@
+//! Jacobian and Hessian helpers built on top of backprop.
+//! They take care of rerunning backward once per output and of resetting every
+//! gradient in the graph between passes, which is easy to get wrong by hand.
+
+use crate::engine::{Float, Value};
+
+/// Computes the Jacobian of a multi-output function, J[i][j] = ∂f_i/∂x_j.
+///
+/// `f` builds its outputs from the leaves in `inputs` (e.g. `MLP::forward`). The graph
+/// is built once and backward is run once per output. Afterwards the gradients of the
+/// leaves and of every node in the graph are left at zero.
+pub fn jacobian<T, F>(f: F, inputs: &[Value<T>]) -> Vec<Vec<T>>
+where
+    T: Float,
+    F: Fn(&[Value<T>]) -> Vec<Value<T>>,
+{
+    let outputs = f(inputs);
+    let rows = outputs
+        .iter()
+        .map(|out| {
+            zero_grads(out, inputs);
+            out.backward();
+            inputs.iter().map(Value::grad).collect()
+        })
+        .collect();
+
+    for out in &outputs {
+        zero_grads(out, inputs);
+    }
+    rows
+}
+
+/// Computes the Hessian of a scalar function, H[i][j] = ∂²f/∂x_i∂x_j.
+///
+/// This is the Jacobian of the gradient, where the gradient is built as a
+/// differentiable graph with [`Value::backward_create_graph`].
+pub fn hessian<T, F>(f: F, inputs: &[Value<T>]) -> Vec<Vec<T>>
+where
+    T: Float,
+    F: Fn(&[Value<T>]) -> Value<T>,
+{
+    jacobian(|x| f(x).backward_create_graph().wrt(x), inputs)
+}
+
+/// Clears the gradients of the whole graph behind `out`, plus the leaves themselves
+/// in case `out` does not depend on some of them.
+fn zero_grads<T: Float>(out: &Value<T>, inputs: &[Value<T>]) {
+    out.zero_grad_graph();
+    for x in inputs {
+        x.set_grad(T::zero());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(data: &[f64]) -> Vec<Value> {
+        data.iter()
+            .map(|&x| Value::new(x, None, "x".to_string(), None))
+            .collect()
+    }
+
+    #[test]
+    fn jacobian_leaves_gradients_at_zero() {
+        let x = leaves(&[0.6, -1.5]);
+        let j = jacobian(|x| vec![&x[0] * &x[1], x[0].sin()], &x);
+        assert_eq!(j, vec![vec![-1.5, 0.6], vec![0.6f64.cos(), 0.0]]);
+        assert_eq!((x[0].grad(), x[1].grad()), (0.0, 0.0));
+    }
+
+    #[test]
+    fn hessian_of_polynomial() {
+        // f(x, y) = x²y + y³ has the Hessian [[2y, 2x], [2x, 6y]]
+        let (x, y) = (0.8, -1.3);
+        let h = hessian(|v| v[0].pow(2.0) * &v[1] + v[1].pow(3.0), &leaves(&[x, y]));
+        let expected = [[2.0 * y, 2.0 * x], [2.0 * x, 6.0 * y]];
+        for (row, expected) in h.iter().zip(expected) {
+            for (a, e) in row.iter().zip(expected) {
+                assert!((a - e).abs() < 1e-12, "{:?}", h);
+            }
+        }
+    }
+}
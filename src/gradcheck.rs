@@ -0,0 +1,338 @@
+//! Numerical gradient checking.
+//! Compares the gradients computed by backprop against central finite differences,
+//! which is the standard way to catch a wrong local derivative in an op.
+
+use std::fmt::Display;
+
+use crate::engine::Value;
+use crate::nn::loss::{self, Reduction};
+use crate::tensor::Tensor;
+
+/// Settings for comparing analytic and numerical gradients.
+pub struct GradCheck {
+    /// Step used for the central difference (f(x + ε) - f(x - ε)) / 2ε
+    pub epsilon: f64,
+    /// Largest accepted error, measured as |analytic - numeric| / max(1, |analytic|, |numeric|)
+    pub tolerance: f64,
+}
+
+/// A leaf whose analytic gradient disagrees with the numerical estimate.
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    /// Position of the leaf in the input slice
+    pub index: usize,
+    pub label: String,
+    pub analytic: f64,
+    pub numeric: f64,
+    pub error: f64,
+}
+
+/// Outcome of a gradient check, with one analytic and numeric gradient per leaf.
+#[derive(Debug, Clone)]
+pub struct GradCheckReport {
+    pub analytic: Vec<f64>,
+    pub numeric: Vec<f64>,
+    pub mismatches: Vec<Mismatch>,
+    /// Largest error seen across all leaves, whether or not it exceeded the tolerance
+    pub max_error: f64,
+}
+
+impl GradCheck {
+    pub fn new(epsilon: f64, tolerance: f64) -> Self {
+        Self { epsilon, tolerance }
+    }
+
+    /// Checks the gradients of `f` with respect to every leaf in `inputs`.
+    ///
+    /// `f` must rebuild its graph from the leaves each time it is called, since the
+    /// leaves are perturbed in place (and restored) to evaluate the finite differences.
+    pub fn check<F>(&self, f: F, inputs: &[Value]) -> GradCheckReport
+    where
+        F: Fn(&[Value]) -> Value,
+    {
+        for x in inputs {
+            x.set_grad(0.0);
+        }
+        f(inputs).backward();
+        let analytic: Vec<f64> = inputs.iter().map(Value::grad).collect();
+
+        let numeric: Vec<f64> = inputs
+            .iter()
+            .map(|x| {
+                let original = x.data();
+                x.set_data(original + self.epsilon);
+                let plus = f(inputs).data();
+                x.set_data(original - self.epsilon);
+                let minus = f(inputs).data();
+                x.set_data(original);
+                (plus - minus) / (2.0 * self.epsilon)
+            })
+            .collect();
+
+        let mut mismatches = Vec::new();
+        let mut max_error: f64 = 0.0;
+        for (index, (&a, &n)) in analytic.iter().zip(&numeric).enumerate() {
+            let error = (a - n).abs() / 1.0_f64.max(a.abs()).max(n.abs());
+            if error.is_nan() || error > self.tolerance {
+                mismatches.push(Mismatch {
+                    index,
+                    label: inputs[index].label(),
+                    analytic: a,
+                    numeric: n,
+                    error,
+                });
+            }
+            max_error = max_error.max(error);
+        }
+
+        GradCheckReport {
+            analytic,
+            numeric,
+            mismatches,
+            max_error,
+        }
+    }
+}
+
+impl Default for GradCheck {
+    fn default() -> Self {
+        Self::new(1e-6, 1e-5)
+    }
+}
+
+impl GradCheckReport {
+    /// Returns true when every leaf's gradient is within tolerance
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl Display for GradCheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_ok() {
+            return write!(f, "ok (max error {:.2e})", self.max_error);
+        }
+        write!(f, "FAILED")?;
+        for m in &self.mismatches {
+            write!(
+                f,
+                "\n    input {} '{}': analytic {:.6}, numeric {:.6} (error {:.2e})",
+                m.index, m.label, m.analytic, m.numeric, m.error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the gradients of `f` with the default epsilon and tolerance.
+pub fn gradcheck<F>(f: F, inputs: &[Value]) -> GradCheckReport
+where
+    F: Fn(&[Value]) -> Value,
+{
+    GradCheck::default().check(f, inputs)
+}
+
+/// A differentiable function of some leaves, checked by [`Case::check`].
+pub type CaseFn = Box<dyn Fn(&[Value]) -> Value>;
+
+/// A named function together with the point its gradients are checked at.
+pub struct Case {
+    pub name: &'static str,
+    pub inputs: Vec<f64>,
+    pub f: CaseFn,
+}
+
+impl Case {
+    /// Checks the gradients of the function at its inputs, labelled `x0`, `x1`, ...
+    pub fn check(&self) -> GradCheckReport {
+        let inputs: Vec<Value> = self
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(i, &d)| Value::new(d, None, format!("x{}", i), None))
+            .collect();
+        gradcheck(&self.f, &inputs)
+    }
+}
+
+/// One case per op, loss and tensor function, shared by the unit tests and the
+/// `gradcheck` mode of the CLI.
+pub fn cases() -> Vec<Case> {
+    // Inputs are chosen away from the kinks of relu, leaky_relu, elu and abs,
+    // where finite differences straddle two different slopes.
+    let cases: Vec<(&'static str, Vec<f64>, CaseFn)> = vec![
+        ("add", vec![1.5, -0.7], Box::new(|x| &x[0] + &x[1])),
+        ("sub", vec![1.5, -0.7], Box::new(|x| &x[0] - &x[1])),
+        ("mul", vec![1.5, -0.7], Box::new(|x| &x[0] * &x[1])),
+        ("div", vec![1.5, -0.7], Box::new(|x| &x[0] / &x[1])),
+        ("neg", vec![0.8], Box::new(|x| -&x[0])),
+        ("pow", vec![1.3], Box::new(|x| x[0].pow(3.0))),
+        ("tanh", vec![0.4], Box::new(|x| x[0].tanh())),
+        ("relu", vec![0.4], Box::new(|x| x[0].relu())),
+        ("relu (negative)", vec![-0.4], Box::new(|x| x[0].relu())),
+        (
+            "leaky_relu",
+            vec![-0.4],
+            Box::new(|x| x[0].leaky_relu(0.01)),
+        ),
+        ("elu", vec![-0.4], Box::new(|x| x[0].elu())),
+        ("exp", vec![0.9], Box::new(|x| x[0].exp())),
+        ("ln", vec![0.9], Box::new(|x| x[0].ln())),
+        ("log2", vec![0.9], Box::new(|x| x[0].log2())),
+        ("sigmoid", vec![-1.2], Box::new(|x| x[0].sigmoid())),
+        ("softplus", vec![-1.2], Box::new(|x| x[0].softplus())),
+        ("gelu", vec![-0.6], Box::new(|x| x[0].gelu())),
+        ("silu", vec![-0.6], Box::new(|x| x[0].silu())),
+        ("abs", vec![-0.6], Box::new(|x| x[0].abs())),
+        ("sqrt", vec![2.3], Box::new(|x| x[0].sqrt())),
+        ("sin", vec![2.3], Box::new(|x| x[0].sin())),
+        ("cos", vec![2.3], Box::new(|x| x[0].cos())),
+        ("sum", vec![1.5, -0.7, 0.2], Box::new(Value::sum)),
+        (
+            "dot",
+            vec![1.5, -0.7, 0.2, 0.9],
+            Box::new(|x| Value::dot(&x[..2], &x[2..])),
+        ),
+        (
+            "logsumexp (large)",
+            vec![1000.0, 999.5, -3.0],
+            Box::new(Value::logsumexp),
+        ),
+        (
+            "log_softmax",
+            vec![0.3, -1.2, 2.0],
+            Box::new(|x| Value::log_softmax(x)[1].clone()),
+        ),
+        (
+            "softmax (large)",
+            vec![800.0, 801.0, 799.0],
+            Box::new(|x| &Value::softmax(x)[0] * 2.0 - &Value::softmax(x)[2]),
+        ),
+        (
+            "mse/mae",
+            vec![0.3, -1.2, 2.0],
+            Box::new(|x| {
+                let t = targets(&[0.5, -1.0, 1.0]);
+                loss::mse(x, &t, Reduction::Mean) + loss::mae(x, &t, Reduction::Sum)
+            }),
+        ),
+        (
+            "huber",
+            vec![0.3, -1.2, 2.0],
+            Box::new(|x| loss::huber(x, &targets(&[0.5, -1.0, 1.0]), 0.5, Reduction::Mean)),
+        ),
+        (
+            "bce",
+            vec![0.3, 0.8, 0.6],
+            Box::new(|x| loss::bce(x, &targets(&[0.0, 1.0, 1.0]), Reduction::Mean)),
+        ),
+        (
+            "bce_with_logits",
+            vec![0.3, -30.0, 40.0],
+            Box::new(|x| loss::bce_with_logits(x, &targets(&[0.0, 1.0, 0.0]), Reduction::Mean)),
+        ),
+        (
+            "cross_entropy",
+            vec![0.3, -1.2, 2.0],
+            Box::new(|x| loss::cross_entropy(x, &targets(&[0.0, 1.0, 0.0]))),
+        ),
+        (
+            "nll/kl_div",
+            vec![0.3, -1.2, 2.0],
+            Box::new(|x| {
+                let t = targets(&[0.2, 0.0, 0.8]);
+                let log_probs = Value::log_softmax(x);
+                loss::nll(&log_probs, &t) + loss::kl_div(&log_probs, &t)
+            }),
+        ),
+        (
+            "hinge",
+            vec![0.3, -1.2, 2.0],
+            Box::new(|x| loss::hinge(x, &targets(&[1.0, 1.0, -1.0]), Reduction::Mean)),
+        ),
+        (
+            "tensor matmul/mean",
+            vec![0.3, -1.2, 0.7, 2.0, -0.5, 0.9],
+            Box::new(|x| {
+                let a = Tensor::new(x.to_vec(), &[2, 3]);
+                let row = Tensor::from(x[..3].to_vec());
+                (a.matmul(&a.transpose()).mean_axis(0).sum() * (&a * &row).mean()).tanh()
+            }),
+        ),
+        (
+            "grad of x*y*sin(x)",
+            vec![1.5, -0.5],
+            Box::new(|x| {
+                let grads = (&x[0] * &x[1] * x[0].sin()).backward_create_graph();
+                grads.wrt(x).into_iter().sum::<Value>()
+            }),
+        ),
+        (
+            "shared subexpression",
+            vec![0.7, -1.1],
+            Box::new(|x| {
+                let a = &x[0] * &x[1];
+                (&a * &a + a.tanh()) * &x[0]
+            }),
+        ),
+    ];
+    cases
+        .into_iter()
+        .map(|(name, inputs, f)| Case { name, inputs, f })
+        .collect()
+}
+
+/// Wraps constant loss targets as Values
+fn targets(data: &[f64]) -> Vec<Value> {
+    data.iter().map(|&t| Value::from(t)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::{CustomOp, CustomOpRef};
+
+    #[test]
+    fn every_case_passes() {
+        let failures: Vec<String> = cases()
+            .iter()
+            .filter_map(|case| {
+                let report = case.check();
+                (!report.is_ok()).then(|| format!("{}: {}", case.name, report))
+            })
+            .collect();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    /// x², with a backward that forgets the factor 2
+    struct BrokenSquare;
+
+    impl CustomOp for BrokenSquare {
+        fn name(&self) -> &str {
+            "broken_square"
+        }
+
+        fn forward(&self, inputs: &[f64]) -> f64 {
+            inputs[0] * inputs[0]
+        }
+
+        fn backward(&self, inputs: &[f64], _out: f64) -> Vec<f64> {
+            vec![inputs[0]]
+        }
+    }
+
+    #[test]
+    fn reports_wrong_gradient() {
+        #[cfg(not(feature = "sync"))]
+        let op: CustomOpRef = std::rc::Rc::new(BrokenSquare);
+        #[cfg(feature = "sync")]
+        let op: CustomOpRef = std::sync::Arc::new(BrokenSquare);
+
+        let x = Value::new(1.5, None, "x".to_string(), None);
+        let report = gradcheck(|x| Value::custom(op.clone(), x), &[x]);
+        assert!(!report.is_ok());
+        assert_eq!(report.mismatches[0].label, "x");
+        assert_eq!(report.mismatches[0].analytic, 1.5);
+        assert!((report.mismatches[0].numeric - 3.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,22 @@
+//! A Rust implementation of Andrej Karpathy's micrograd - a tiny autograd engine
+//! that implements backpropagation (reverse-mode automatic differentiation) over
+//! a dynamically built DAG. This allows for training neural networks with a
+//! minimal yet feature-complete implementation.
+
+mod draw;
+pub mod dual;
+pub mod engine;
+pub mod float;
+pub mod functional;
+pub mod gradcheck;
+pub mod nn;
+pub mod op;
+pub mod optim;
+#[cfg(feature = "sync")]
+pub mod parallel;
+pub mod scalar;
+pub mod tape;
+pub mod tensor;
+pub mod viz;
+
+pub use engine::Value;
@@ -1,24 +1,27 @@
-//! A Rust implementation of Andrej Karpathy's micrograd - a tiny autograd engine
-//! that implements backpropagation (reverse-mode automatic differentiation) over
-//! a dynamically built DAG. This allows for training neural networks with a
-//! minimal yet feature-complete implementation.
+//! Command line driver for the micrograd-rs examples.
 
 use anyhow::Result;
 use clap::{Parser, ValueEnum};
-use viz::BackpropViz;
-
-use engine::Value;
 use rand::prelude::SliceRandom;
-
-use rand::thread_rng;
-use viz::load_training_data;
-
-use crate::nn::Module;
-
-mod draw;
-mod engine;
-mod nn;
-mod viz;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use micrograd_rs::dual::{self, Dual};
+use micrograd_rs::engine::{no_grad, Float, Value};
+use micrograd_rs::functional::{hessian, jacobian};
+use micrograd_rs::gradcheck::{self, gradcheck};
+use micrograd_rs::nn::init::Init;
+use micrograd_rs::nn::loss::{self, Reduction};
+use micrograd_rs::nn::{self, Activation, Dropout, Forward, LayerNorm, Module, Sequential};
+use micrograd_rs::optim::lr_scheduler::{
+    Constant, CosineWarmRestarts, ExponentialDecay, LinearWarmup, LrScheduler, OneCycle,
+    ReduceOnPlateau, StepDecay,
+};
+use micrograd_rs::optim::{Adagrad, Adam, AdamW, Optimizer, RmsProp, Sgd};
+use micrograd_rs::tape;
+use micrograd_rs::viz::{self, load_training_data, BackpropViz, Sample};
 
 #[derive(Parser)]
 #[command(author, version, about)]
@@ -28,12 +31,128 @@ struct Args {
 
     #[arg(short, long)]
     visualize: bool,
+
+    /// Train on the arena/tape backend instead of `Rc`-based Values
+    #[arg(long)]
+    tape: bool,
+
+    /// Train on mini-batches whose samples are differentiated in parallel
+    /// (requires building with `--features sync`)
+    #[arg(long)]
+    parallel: bool,
+
+    /// Scalar type the network is trained in
+    #[arg(long, value_enum, default_value_t = Precision::F64)]
+    precision: Precision,
+
+    /// Optimizer used to train the network
+    #[arg(long, value_enum, default_value_t = OptimizerKind::Sgd)]
+    optimizer: OptimizerKind,
+
+    /// Learning rate (defaults to a value suited to the chosen optimizer)
+    #[arg(long)]
+    lr: Option<f64>,
+
+    /// How the learning rate changes from epoch to epoch
+    #[arg(long, value_enum, default_value_t = Schedule::Constant)]
+    scheduler: Schedule,
+
+    /// Seed for weight initialization and the dataset shuffle, making runs
+    /// reproducible (random if omitted)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Write the trained network to this file (JSON if it ends in `.json`, else binary)
+    #[arg(long)]
+    save_model: Option<PathBuf>,
+
+    /// Evaluate a network saved with `--save-model` instead of training a new one
+    #[arg(long)]
+    load_model: Option<PathBuf>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
 enum Mode {
     Val,
     Nn,
+    Gradcheck,
+    Bench,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Precision {
+    F32,
+    F64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum OptimizerKind {
+    Sgd,
+    /// SGD with Nesterov momentum
+    Momentum,
+    Adam,
+    Adamw,
+    Rmsprop,
+    Adagrad,
+}
+
+impl OptimizerKind {
+    fn default_lr(self) -> f64 {
+        match self {
+            OptimizerKind::Sgd | OptimizerKind::Adagrad => 0.1,
+            OptimizerKind::Momentum => 0.05,
+            OptimizerKind::Adam | OptimizerKind::Adamw | OptimizerKind::Rmsprop => 0.01,
+        }
+    }
+
+    fn build<T: Float + 'static>(self, params: Vec<Value<T>>, lr: T) -> Box<dyn Optimizer<T>> {
+        match self {
+            OptimizerKind::Sgd => Box::new(Sgd::new(params, lr)),
+            OptimizerKind::Momentum => Box::new(
+                Sgd::new(params, lr)
+                    .momentum(T::from_f64(0.9))
+                    .nesterov(true),
+            ),
+            OptimizerKind::Adam => Box::new(Adam::new(params, lr)),
+            OptimizerKind::Adamw => Box::new(AdamW::new(params, lr)),
+            OptimizerKind::Rmsprop => Box::new(RmsProp::new(params, lr)),
+            OptimizerKind::Adagrad => Box::new(Adagrad::new(params, lr)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Schedule {
+    Constant,
+    /// Halve the rate every 30 epochs
+    Step,
+    /// Decay the rate by 2% per epoch
+    Exponential,
+    /// Cosine annealing, restarting after 20 and then 60 more epochs
+    Cosine,
+    /// Linear warmup over 10 epochs, then cosine annealing
+    Warmup,
+    OneCycle,
+    /// Halve the rate when the loss stops improving for 5 epochs
+    Plateau,
+}
+
+impl Schedule {
+    fn build<T: Float + 'static>(self, lr: T, epochs: usize) -> Box<dyn LrScheduler<T>> {
+        let half = T::from_f64(0.5);
+        match self {
+            Schedule::Constant => Box::new(Constant::new(lr)),
+            Schedule::Step => Box::new(StepDecay::new(lr, 30, half)),
+            Schedule::Exponential => Box::new(ExponentialDecay::new(lr, T::from_f64(0.98))),
+            Schedule::Cosine => Box::new(CosineWarmRestarts::new(lr, 20).t_mult(3)),
+            Schedule::Warmup => Box::new(
+                LinearWarmup::new(CosineWarmRestarts::new(lr, epochs - 10), 10)
+                    .start_factor(T::from_f64(0.1)),
+            ),
+            Schedule::OneCycle => Box::new(OneCycle::new(lr, epochs)),
+            Schedule::Plateau => Box::new(ReduceOnPlateau::new(lr).factor(half).patience(5)),
+        }
+    }
 }
 
 fn main() -> Result<()> {
@@ -45,7 +164,12 @@ fn main() -> Result<()> {
 
     match args.mode {
         Mode::Val => run_values_example(args.visualize)?,
-        Mode::Nn => run_nn_example()?,
+        Mode::Nn => match args.precision {
+            Precision::F32 => run_nn_example::<f32>(&args)?,
+            Precision::F64 => run_nn_example::<f64>(&args)?,
+        },
+        Mode::Gradcheck => run_gradcheck()?,
+        Mode::Bench => run_bench()?,
     }
     Ok(())
 }
@@ -60,7 +184,7 @@ fn run_values_example(visualize: bool) -> Result<()> {
     let w2 = Value::new(1.0, None, "w2".to_string(), None);
 
     // bias of the neuron
-    let b = Value::new(6.8813735870195432, None, "b".to_string(), None);
+    let b = Value::new(6.881_373_587_019_543, None, "b".to_string(), None);
 
     // compute neuron activation
     let x1w1 = &x1 * &w1;
@@ -97,51 +221,58 @@ fn run_values_example(visualize: bool) -> Result<()> {
     Ok(())
 }
 
-fn run_nn_example() -> Result<()> {
+fn run_nn_example<T: Float + Send + Sync + 'static>(args: &Args) -> Result<()> {
     // Load dataset from CSV
-    let mut xs = load_training_data("xor_data.csv")?;
+    let mut xs = load_training_data::<T>("xor_data.csv")?;
+
+    // One seeded generator drives the shuffle and the initial weights
+    let seed = args.seed.unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
 
     // Shuffle the dataset
-    xs.shuffle(&mut thread_rng());
+    xs.shuffle(&mut rng);
 
     // Calculate split index (80% train, 20% test)
     let split_idx = (xs.len() as f64 * 0.8) as usize;
     let (train_data, test_data) = xs.split_at(split_idx);
 
-    // Create a 2-layer neural network (2->4->1)
-    let mut model = nn::MLP::new(2, &vec![4, 1]);
-    let mut losses: Vec<f64> = Vec::new();
-
-    // Training loop
-    for epoch in 0..100 {
-        let mut epoch_loss = 0.0;
-
-        for (x, y) in train_data {
-            let pred = model.forward(x.to_vec())[0].clone();
-            let loss = (&pred - y).pow(2.0);
-            epoch_loss += loss.data();
-            model.zero_grad();
-            loss.backward();
-            model.update_weights(0.1);
+    let model = match &args.load_model {
+        Some(path) => {
+            let model = nn::MLP::load(path)?;
+            let shape = (model.layers().first(), model.layers().last());
+            anyhow::ensure!(
+                matches!(shape, (Some(first), Some(last)) if first.nin() == 2 && last.nout() == 1),
+                "{} does not map 2 inputs to 1 output",
+                path.display()
+            );
+            println!("Loaded model from {}", path.display());
+            model
         }
-
-        epoch_loss /= train_data.len() as f64;
-        losses.push(epoch_loss);
-
-        if epoch % 10 == 0 {
-            println!("Epoch {}: Loss = {:.4}", epoch, epoch_loss);
+        None => {
+            // Create a 2-layer neural network (2->4->1) with tanh hidden units and a sigmoid output
+            let model = nn::MLP::builder(2)
+                .layer(4, Activation::Tanh)
+                .layer(1, Activation::Sigmoid)
+                .build(&mut rng);
+            train(args, &model, train_data)?;
+            model
         }
-    }
+    };
 
-    viz::plot_losses(&losses, "training_loss.png")?;
+    if let Some(path) = &args.save_model {
+        model.save(path)?;
+        println!("Saved model to {}", path.display());
+    }
 
     // Evaluate on test set
     println!("\n--- Test Set Evaluation ---");
     let mut test_correct = 0;
     let mut test_error = 0.0;
     for (x, y) in test_data {
-        let pred = model.forward(x.to_vec())[0].clone();
-        let error = (pred.data() - y.data()).abs();
+        let input: Vec<T> = x.iter().map(Value::data).collect();
+        let pred = model.predict(&input)[0];
+        let error = (pred - y.data()).to_f64().abs();
         test_error += error;
         if error < 0.5 {
             test_correct += 1;
@@ -151,7 +282,7 @@ fn run_nn_example() -> Result<()> {
             x[0].data(),
             x[1].data(),
             y.data(),
-            pred.data()
+            pred
         );
     }
     println!(
@@ -165,3 +296,296 @@ fn run_nn_example() -> Result<()> {
 
     Ok(())
 }
+
+/// Trains `model` on `train_data` with the optimizer and schedule chosen on the command
+/// line, and plots the loss of every epoch.
+fn train<T: Float + Send + Sync + 'static>(
+    args: &Args,
+    model: &nn::MLP<T>,
+    train_data: &[Sample<T>],
+) -> Result<()> {
+    let mut losses: Vec<f64> = Vec::new();
+    let learning_rate = args.lr.unwrap_or_else(|| args.optimizer.default_lr());
+    let epochs = 100;
+    let mut scheduler = args.scheduler.build(T::from_f64(learning_rate), epochs);
+    // Create the optimizer with the base rate, which parameter groups are relative to,
+    // then start from the schedule's first rate
+    let mut optimizer = args
+        .optimizer
+        .build(model.parameters(), T::from_f64(learning_rate));
+    optimizer.set_learning_rate(scheduler.lr());
+
+    // Training loop
+    for epoch in 0..epochs {
+        let lr = optimizer.learning_rate();
+        let mut epoch_loss = T::zero();
+
+        if args.parallel {
+            epoch_loss = train_epoch_parallel(model, optimizer.as_mut(), train_data)?;
+        } else {
+            for (x, y) in train_data {
+                optimizer.zero_grad();
+                if args.tape {
+                    epoch_loss += tape::backward(|graph| {
+                        let x = x.iter().map(|xi| graph.var(xi.data())).collect();
+                        let pred = model.forward_with(x, &mut |p| graph.param(p));
+                        loss::mse(&pred, &[graph.var(y.data())], Reduction::Mean)
+                    });
+                } else {
+                    let pred = model.forward(x);
+                    let loss = loss::mse(&pred, std::slice::from_ref(y), Reduction::Mean);
+                    epoch_loss += loss.data();
+                    loss.backward();
+                }
+                optimizer.step();
+            }
+        }
+
+        let epoch_loss = epoch_loss.to_f64() / train_data.len() as f64;
+        losses.push(epoch_loss);
+
+        if epoch % 10 == 0 {
+            println!("Epoch {}: Loss = {:.4}, LR = {:.5}", epoch, epoch_loss, lr);
+        }
+        scheduler.update(optimizer.as_mut(), T::from_f64(epoch_loss));
+    }
+
+    viz::plot_losses(&losses, "training_loss.png")?;
+    Ok(())
+}
+
+/// Number of samples whose gradients are averaged into one update with `--parallel`
+#[cfg(feature = "sync")]
+const BATCH_SIZE: usize = 8;
+
+/// Runs one epoch of mini-batch training, differentiating the samples of each batch on
+/// rayon workers, and returns the summed loss of the epoch.
+#[cfg(feature = "sync")]
+fn train_epoch_parallel<T: Float + Send + Sync>(
+    model: &nn::MLP<T>,
+    optimizer: &mut dyn Optimizer<T>,
+    train_data: &[Sample<T>],
+) -> Result<T> {
+    use micrograd_rs::parallel::batch_gradients;
+
+    let params = optimizer.parameters().to_vec();
+    let mut epoch_loss = T::zero();
+    for batch in train_data.chunks(BATCH_SIZE) {
+        let (loss, grads) = batch_gradients(&params, batch, |(x, y)| {
+            loss::mse(&model.forward(x), std::slice::from_ref(y), Reduction::Mean)
+        });
+        epoch_loss += loss;
+        let batch_len = T::from_f64(batch.len() as f64);
+        for (p, g) in params.iter().zip(grads) {
+            p.set_grad(g / batch_len);
+        }
+        optimizer.step();
+    }
+    Ok(epoch_loss)
+}
+
+#[cfg(not(feature = "sync"))]
+fn train_epoch_parallel<T: Float + Send + Sync>(
+    _model: &nn::MLP<T>,
+    _optimizer: &mut dyn Optimizer<T>,
+    _train_data: &[Sample<T>],
+) -> Result<T> {
+    anyhow::bail!("--parallel requires building with `--features sync`")
+}
+
+fn run_gradcheck() -> Result<()> {
+    let mut failures = 0;
+    for case in gradcheck::cases() {
+        let report = case.check();
+        if !report.is_ok() {
+            failures += 1;
+        }
+        println!("{:<22} {}", case.name, report);
+    }
+
+    // A randomly initialised network, checked with respect to all of its parameters
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut model = nn::MLP::new(3, &[4, 4, 2], &mut rng);
+    let x: Vec<Value> = [0.5, -1.2, 0.8]
+        .iter()
+        .map(|&d| Value::new(d, None, "x".to_string(), None))
+        .collect();
+    let report = gradcheck(
+        |_| model.forward(&x).into_iter().sum::<Value>(),
+        &model.parameters(),
+    );
+    if !report.is_ok() {
+        failures += 1;
+    }
+    println!("{:<22} {}", "mlp", report);
+
+    // Cross-entropy of the softmax over the network outputs, w.r.t. the parameters
+    let report = gradcheck(
+        |_| -Value::log_softmax(&model.forward(&x))[1].clone(),
+        &model.parameters(),
+    );
+    if !report.is_ok() {
+        failures += 1;
+    }
+    println!("{:<22} {}", "mlp log_softmax", report);
+
+    // Every activation, ending in a softmax output layer
+    let activations = nn::MLP::builder(3)
+        .layer(4, Activation::Tanh)
+        .layer(4, Activation::Gelu)
+        .layer(4, Activation::LeakyRelu(0.1))
+        .layer(4, Activation::Sigmoid)
+        .layer(3, Activation::Softmax)
+        .build(&mut rng);
+    let report = gradcheck(
+        |_| activations.forward(&x)[0].clone(),
+        &activations.parameters(),
+    );
+    if !report.is_ok() {
+        failures += 1;
+    }
+    println!("{:<22} {}", "mlp activations", report);
+
+    // Modules chained in a Sequential, with dropout in evaluation mode
+    let mut linear = |nin, nout| {
+        nn::Layer::new(
+            nin,
+            nout,
+            Activation::Identity,
+            &Init::XavierNormal,
+            &mut rng,
+        )
+    };
+    let (hidden, output) = (linear(3, 4), linear(4, 2));
+    let mut sequential = Sequential::new()
+        .with(hidden)
+        .with(LayerNorm::new(4))
+        .with(Activation::Tanh)
+        .with(Dropout::new(0.5, &mut rng))
+        .with(output);
+    sequential.set_training(false);
+    let report = gradcheck(
+        |_| sequential.forward(&x).into_iter().sum::<Value>(),
+        &sequential.parameters(),
+    );
+    if !report.is_ok() {
+        failures += 1;
+    }
+    println!("{:<22} {}", "sequential", report);
+
+    // Cross-check the reverse-mode Jacobian w.r.t. the inputs against forward mode,
+    // where each output's tangents form one row of the Jacobian
+    let reverse = jacobian(|x| model.forward(x), &x);
+    let forward = dual::jacobian(
+        |xs| model.forward_with(xs, &mut |p| Dual::constant(p.data())),
+        &[x[0].data(), x[1].data(), x[2].data()],
+    );
+    failures += report_max_error("mlp jacobian", &reverse, &forward);
+
+    // The tape backend must produce the same parameter gradients as the Value graph
+    let params = model.parameters();
+    model.zero_grad();
+    model.forward(&x).into_iter().sum::<Value>().backward();
+    let value_grads = vec![params.iter().map(Value::grad).collect::<Vec<f64>>()];
+    model.zero_grad();
+    tape::backward(|graph| {
+        let xs = x.iter().map(|xi| graph.var(xi.data())).collect();
+        let outs = model.forward_with(xs, &mut |p| graph.param(p));
+        outs.into_iter()
+            .reduce(|acc, o| acc + o)
+            .expect("model has outputs")
+    });
+    let tape_grads = vec![params.iter().map(Value::grad).collect::<Vec<f64>>()];
+    failures += report_max_error("mlp tape vs value", &tape_grads, &value_grads);
+
+    // f(x, y) = x²y + y³ has the Hessian [[2y, 2x], [2x, 6y]]
+    let xy = [
+        Value::new(0.8, None, "x".to_string(), None),
+        Value::new(-1.3, None, "y".to_string(), None),
+    ];
+    let (x0, x1) = (xy[0].data(), xy[1].data());
+    let expected = vec![vec![2.0 * x1, 2.0 * x0], vec![2.0 * x0, 6.0 * x1]];
+    let h = hessian(|x| x[0].pow(2.0) * &x[1] + x[1].pow(3.0), &xy);
+    failures += report_max_error("hessian", &h, &expected);
+
+    if failures > 0 {
+        anyhow::bail!("{} gradient check(s) failed", failures);
+    }
+    Ok(())
+}
+
+/// Prints how far two matrices are apart and returns 1 if they disagree.
+fn report_max_error(name: &str, actual: &[Vec<f64>], expected: &[Vec<f64>]) -> usize {
+    let max_error = actual
+        .iter()
+        .flatten()
+        .zip(expected.iter().flatten())
+        .map(|(a, e)| (a - e).abs())
+        .fold(0.0, f64::max);
+    if max_error > 1e-9 {
+        println!("{:<22} FAILED (max error {:.2e})", name, max_error);
+        1
+    } else {
+        println!("{:<22} ok (max error {:.2e})", name, max_error);
+        0
+    }
+}
+
+fn run_bench() -> Result<()> {
+    let data = load_training_data("xor_data.csv")?;
+    let inputs: Vec<Vec<f64>> = data
+        .iter()
+        .map(|(x, _)| x.iter().map(Value::data).collect())
+        .collect();
+    let model = nn::MLP::new(2, &[16, 16, 1], &mut StdRng::seed_from_u64(0));
+    let rounds = 200;
+
+    println!(
+        "Inference over {} samples x {} rounds",
+        inputs.len(),
+        rounds
+    );
+    let graph = bench("forward (graph)", None, || {
+        for _ in 0..rounds {
+            for x in &inputs {
+                let x: Vec<Value> = x.iter().map(|&v| Value::from(v)).collect();
+                model.forward(&x);
+            }
+        }
+    });
+    bench("forward (no_grad)", Some(graph), || {
+        let _guard = no_grad();
+        for _ in 0..rounds {
+            for x in &inputs {
+                let x: Vec<Value> = x.iter().map(|&v| Value::from(v)).collect();
+                model.forward(&x);
+            }
+        }
+    });
+    bench("predict", Some(graph), || {
+        for _ in 0..rounds {
+            for x in &inputs {
+                model.predict(x);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Times `f` and prints the result, with the speedup over `baseline` if given.
+fn bench<F: FnMut()>(name: &str, baseline: Option<Duration>, mut f: F) -> Duration {
+    let start = Instant::now();
+    f();
+    let elapsed = start.elapsed();
+    match baseline {
+        Some(baseline) => println!(
+            "{:<22} {:>10.2?} ({:.1}x faster)",
+            name,
+            elapsed,
+            baseline.as_secs_f64() / elapsed.as_secs_f64()
+        ),
+        None => println!("{:<22} {:>10.2?}", name, elapsed),
+    }
+    elapsed
+}
@@ -1,128 +1,531 @@
-use crate::engine::Value;
-use rand::Rng;
+pub mod checkpoint;
+pub mod init;
+mod layers;
+pub mod loss;
+mod sequential;
 
-/// Base trait for neural network modules
-pub trait Module {
-    fn parameters(&self) -> Vec<Value>;
+pub use layers::{Dropout, LayerNorm};
+pub use sequential::Sequential;
+
+use crate::engine::{Float, Value};
+use crate::scalar::Scalar;
+use crate::tensor::Tensor;
+use anyhow::{bail, Result};
+use init::Init;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+/// Parameter values keyed by their names from [`Module::named_parameters`]
+pub type StateDict<T = f64> = BTreeMap<String, T>;
+
+/// Base trait for neural network modules, whose parameters hold numbers of type `T`
+pub trait Module<T: Float = f64> {
+    fn parameters(&self) -> Vec<Value<T>>;
+
+    /// The parameters in the same order as [`Module::parameters`], each with a dotted
+    /// path naming where it lives, like `layers.1.neurons.3.w.0`. Defaults to the
+    /// parameter's index.
+    fn named_parameters(&self) -> Vec<(String, Value<T>)> {
+        self.parameters()
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| (i.to_string(), p))
+            .collect()
+    }
 
     fn zero_grad(&mut self) {
         for p in self.parameters() {
-            p.set_grad(0.0);
+            p.set_grad(T::zero());
+        }
+    }
+
+    /// Stops backprop from computing gradients for the parameters, so optimizers
+    /// leave them unchanged. Parameters are shared handles, so freezing e.g.
+    /// `model.layers()[0]` freezes that layer inside the model.
+    fn freeze(&self) {
+        for p in self.parameters() {
+            p.set_requires_grad(false);
+        }
+    }
+
+    /// Makes every parameter trainable again, undoing [`Module::freeze`]
+    fn unfreeze(&self) {
+        for p in self.parameters() {
+            p.set_requires_grad(true);
+        }
+    }
+
+    /// The parameters that are not frozen
+    fn trainable_parameters(&self) -> Vec<Value<T>> {
+        self.parameters()
+            .into_iter()
+            .filter(Value::requires_grad)
+            .collect()
+    }
+
+    /// Switches between training and evaluation behaviour, for modules such as
+    /// [`Dropout`] that act differently in each. Does nothing by default.
+    fn set_training(&mut self, _training: bool) {}
+
+    /// Snapshot of the current value of every named parameter
+    fn state_dict(&self) -> StateDict<T> {
+        self.named_parameters()
+            .into_iter()
+            .map(|(name, p)| (name, p.data()))
+            .collect()
+    }
+
+    /// Sets every parameter from `state`, which must hold exactly this module's
+    /// parameter names. Nothing is changed if a name is missing or unexpected.
+    fn load_state_dict(&mut self, state: &StateDict<T>) -> Result<()> {
+        let params = self.named_parameters();
+        let missing: Vec<&str> = params
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| !state.contains_key(*name))
+            .collect();
+        if !missing.is_empty() {
+            bail!("missing parameters in state dict: {}", missing.join(", "));
+        }
+        if state.len() != params.len() {
+            let known: Vec<&str> = params.iter().map(|(name, _)| name.as_str()).collect();
+            let unexpected: Vec<&str> = state
+                .keys()
+                .map(String::as_str)
+                .filter(|name| !known.contains(name))
+                .collect();
+            bail!(
+                "unexpected parameters in state dict: {}",
+                unexpected.join(", ")
+            );
+        }
+        for (name, p) in params {
+            p.set_data(state[&name]);
+        }
+        Ok(())
+    }
+}
+
+/// A module mapping a vector of inputs to a vector of outputs, which lets modules be
+/// chained in a [`Sequential`].
+pub trait Forward<T: Float = f64>: Module<T> {
+    fn forward(&self, x: &[Value<T>]) -> Vec<Value<T>>;
+}
+
+/// Names the parameters of a child module `prefix.<name>`
+fn prefix_names<T: Float>(
+    prefix: String,
+    named: Vec<(String, Value<T>)>,
+) -> impl Iterator<Item = (String, Value<T>)> {
+    named
+        .into_iter()
+        .map(move |(name, p)| (format!("{}.{}", prefix, name), p))
+}
+
+/// Labels every parameter with its name, so drawn graphs show where it lives
+fn label_parameters<T: Float, M: Module<T>>(module: &M) {
+    for (name, p) in module.named_parameters() {
+        p.set_label(name);
+    }
+}
+
+/// Nonlinearity applied to the outputs of a [`Layer`].
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Activation {
+    #[default]
+    Relu,
+    Tanh,
+    Sigmoid,
+    /// ReLU with the given slope for negative inputs
+    LeakyRelu(f64),
+    Gelu,
+    /// No nonlinearity, e.g. for a regression output
+    Identity,
+    /// Softmax over all outputs of the layer, turning them into a probability
+    /// distribution; only meaningful on the output layer
+    Softmax,
+}
+
+impl Activation {
+    /// Applies the activation to a layer's pre-activations
+    pub fn apply<T: Float, S: Scalar<T>>(self, z: Vec<S>) -> Vec<S> {
+        match self {
+            Activation::Relu => z.iter().map(S::relu).collect(),
+            Activation::Tanh => z.iter().map(S::tanh).collect(),
+            Activation::Sigmoid => z.iter().map(S::sigmoid).collect(),
+            Activation::LeakyRelu(alpha) => {
+                z.iter().map(|x| x.leaky_relu(T::from_f64(alpha))).collect()
+            }
+            Activation::Gelu => z.iter().map(S::gelu).collect(),
+            Activation::Identity => z,
+            Activation::Softmax => S::softmax(&z),
         }
     }
 }
 
-/// Single neuron with weights, bias, and optional nonlinearity
-pub struct Neuron {
-    w: Vec<Value>,
-    b: Value,
-    nonlin: bool,
+/// An activation is a module without parameters, so it can be placed between the
+/// layers of a [`Sequential`].
+impl<T: Float> Module<T> for Activation {
+    fn parameters(&self) -> Vec<Value<T>> {
+        Vec::new()
+    }
+}
+
+impl<T: Float> Forward<T> for Activation {
+    fn forward(&self, x: &[Value<T>]) -> Vec<Value<T>> {
+        self.apply(x.to_vec())
+    }
+}
+
+/// Single neuron computing the affine function `w·x + b`; the [`Layer`] it belongs
+/// to applies the activation.
+pub struct Neuron<T: Float = f64> {
+    w: Vec<Value<T>>,
+    b: Value<T>,
 }
 
-impl Neuron {
-    pub fn new(nin: usize, nonlin: bool) -> Self {
-        let mut rng = rand::thread_rng();
+impl<T: Float> Neuron<T> {
+    /// Creates a neuron with the given initial weights and bias
+    pub fn new(w: Vec<T>, b: T) -> Self {
         Self {
-            w: (0..nin)
-                .map(|i| Value::new(rng.gen_range(-1.0..1.0), None, format!("w{}", i), None))
+            w: w.into_iter()
+                .enumerate()
+                .map(|(i, w)| Value::new(w, None, format!("w.{}", i), None))
                 .collect(),
-            b: Value::new(0.0, None, "b".to_string(), None),
-            nonlin,
+            b: Value::new(b, None, "b".to_string(), None),
         }
     }
 
-    pub fn forward(&self, x: &[Value]) -> Value {
-        let mut act = self.b.clone();
-        for (wi, xi) in self.w.iter().zip(x.iter()) {
-            act = &act + &(wi * xi);
-        }
-        if self.nonlin {
-            act.relu()
-        } else {
-            act
-        }
+    pub fn forward(&self, x: &[Value<T>]) -> Value<T> {
+        self.forward_with(x, &mut Value::clone)
+    }
+
+    /// Evaluates the neuron on any [`Scalar`] type, lifting each parameter into that
+    /// type with `param` (e.g. as a constant [`Dual`](crate::dual::Dual)).
+    pub fn forward_with<S, F>(&self, x: &[S], param: &mut F) -> S
+    where
+        S: Scalar<T>,
+        F: FnMut(&Value<T>) -> S,
+    {
+        let b = param(&self.b);
+        let w: Vec<S> = self.w.iter().map(&mut *param).collect();
+        S::dot(&w, x) + b
     }
 }
 
-impl Module for Neuron {
-    fn parameters(&self) -> Vec<Value> {
+impl<T: Float> Module<T> for Neuron<T> {
+    fn parameters(&self) -> Vec<Value<T>> {
         let mut params = self.w.clone();
         params.push(self.b.clone());
         params
     }
+
+    fn named_parameters(&self) -> Vec<(String, Value<T>)> {
+        let mut named: Vec<_> = self
+            .w
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (format!("w.{}", i), w.clone()))
+            .collect();
+        named.push(("b".to_string(), self.b.clone()));
+        named
+    }
 }
 
-/// Layer of neurons
-pub struct Layer {
-    neurons: Vec<Neuron>,
+/// Layer of neurons followed by an activation
+pub struct Layer<T: Float = f64> {
+    neurons: Vec<Neuron<T>>,
+    activation: Activation,
 }
 
-impl Layer {
-    pub fn new(nin: usize, nout: usize, nonlin: bool) -> Self {
-        Self {
-            neurons: (0..nout).map(|_| Neuron::new(nin, nonlin)).collect(),
-        }
+impl<T: Float> Layer<T> {
+    /// Creates a layer of `nout` neurons with weights drawn by `init` from `rng` and
+    /// zero biases.
+    pub fn new(
+        nin: usize,
+        nout: usize,
+        activation: Activation,
+        init: &Init,
+        rng: &mut dyn RngCore,
+    ) -> Self {
+        let w = init.weights(nin, nout, rng);
+        let neurons = (0..nout)
+            .map(|i| {
+                let row = w[i * nin..(i + 1) * nin].iter();
+                Neuron::new(row.map(|&w| T::from_f64(w)).collect(), T::zero())
+            })
+            .collect();
+        let layer = Self {
+            neurons,
+            activation,
+        };
+        label_parameters(&layer);
+        layer
     }
 
-    pub fn forward(&self, x: &[Value]) -> Vec<Value> {
-        self.neurons.iter().map(|n| n.forward(x)).collect()
+    /// Number of inputs
+    pub fn nin(&self) -> usize {
+        self.neurons.first().map_or(0, |n| n.w.len())
+    }
+
+    /// Number of outputs
+    pub fn nout(&self) -> usize {
+        self.neurons.len()
+    }
+
+    pub fn activation(&self) -> Activation {
+        self.activation
+    }
+
+    /// Evaluates the layer on any [`Scalar`] type, see [`Neuron::forward_with`].
+    ///
+    /// The weights of all neurons are stacked into one matrix, so the pre-activations
+    /// are a single matrix-vector product `Wx + b`.
+    pub fn forward_with<S, F>(&self, x: &[S], param: &mut F) -> Vec<S>
+    where
+        S: Scalar<T>,
+        F: FnMut(&Value<T>) -> S,
+    {
+        let nin = self.nin();
+        let mut w = Vec::with_capacity(self.neurons.len() * nin);
+        let mut b = Vec::with_capacity(self.neurons.len());
+        for n in &self.neurons {
+            b.push(param(&n.b));
+            w.extend(n.w.iter().map(&mut *param));
+        }
+        let w = Tensor::new(w, &[self.neurons.len(), nin]);
+        let z = w.matmul(&Tensor::from(x.to_vec())) + Tensor::from(b);
+        self.activation.apply(z.into_vec())
     }
 }
 
-impl Module for Layer {
-    fn parameters(&self) -> Vec<Value> {
+impl<T: Float> Module<T> for Layer<T> {
+    fn parameters(&self) -> Vec<Value<T>> {
         self.neurons.iter().flat_map(|n| n.parameters()).collect()
     }
+
+    fn named_parameters(&self) -> Vec<(String, Value<T>)> {
+        self.neurons
+            .iter()
+            .enumerate()
+            .flat_map(|(i, n)| prefix_names(format!("neurons.{}", i), n.named_parameters()))
+            .collect()
+    }
 }
 
-/// Multi-layer perceptron
-pub struct MLP {
-    layers: Vec<Layer>,
+impl<T: Float> Forward<T> for Layer<T> {
+    fn forward(&self, x: &[Value<T>]) -> Vec<Value<T>> {
+        self.forward_with(x, &mut Value::clone)
+    }
 }
 
-impl MLP {
-    pub fn new(nin: usize, nouts: &[usize]) -> Self {
-        let mut sizes = vec![nin];
-        sizes.extend_from_slice(nouts);
+/// Multi-layer perceptron. Parameters are `f64` by default; use e.g.
+/// `MLP::<f32>::new` to train in single precision.
+#[allow(clippy::upper_case_acronyms)]
+pub struct MLP<T: Float = f64> {
+    /// Kept apart from the layers so that a network without any still has a size
+    nin: usize,
+    layers: Vec<Layer<T>>,
+}
 
-        let layers = (0..nouts.len())
-            .map(|i| {
-                Layer::new(
-                    sizes[i],
-                    sizes[i + 1],
-                    i != nouts.len() - 1, // nonlin=false for last layer
-                )
-            })
-            .collect();
+impl<T: Float> MLP<T> {
+    /// Creates a network with ReLU hidden layers and a linear output layer, drawing the
+    /// initial weights from `rng`. Use [`MLP::builder`] to choose the activation and
+    /// initialization of each layer.
+    pub fn new(nin: usize, nouts: &[usize], rng: &mut dyn RngCore) -> Self {
+        let mut builder = Self::builder(nin);
+        for (i, &nout) in nouts.iter().enumerate() {
+            let activation = if i + 1 < nouts.len() {
+                Activation::Relu
+            } else {
+                Activation::Identity
+            };
+            builder = builder.layer(nout, activation);
+        }
+        builder.build(rng)
+    }
+
+    /// Starts building a network with `nin` inputs, one layer at a time.
+    ///
+    /// Panics if `nin` is zero.
+    pub fn builder(nin: usize) -> MlpBuilder<T> {
+        assert!(nin > 0, "a network needs at least one input");
+        MlpBuilder {
+            nin,
+            layers: Vec::new(),
+            _scalar: PhantomData,
+        }
+    }
+
+    /// Number of inputs
+    pub fn nin(&self) -> usize {
+        self.nin
+    }
 
-        Self { layers }
+    pub fn layers(&self) -> &[Layer<T>] {
+        &self.layers
     }
 
-    pub fn forward(&self, mut x: Vec<Value>) -> Vec<Value> {
+    /// Evaluates the network on any [`Scalar`] type, see [`Neuron::forward_with`].
+    pub fn forward_with<S, F>(&self, mut x: Vec<S>, param: &mut F) -> Vec<S>
+    where
+        S: Scalar<T>,
+        F: FnMut(&Value<T>) -> S,
+    {
         for layer in &self.layers {
-            x = layer.forward(&x);
+            x = layer.forward_with(&x, param);
         }
         x
     }
 
-    pub fn update_weights(&mut self, learning_rate: f64) {
-        for layer in &mut self.layers {
-            for neuron in &mut layer.neurons {
-                // Update weights
-                for w in &mut neuron.w {
-                    let grad = w.grad();
-                    w.set_data(w.data() - learning_rate * grad);
-                }
-                // Update bias
-                let grad = neuron.b.grad();
-                neuron.b.set_data(neuron.b.data() - learning_rate * grad);
-            }
-        }
+    /// Fast inference path: evaluates the network on plain numbers without
+    /// building a computation graph.
+    pub fn predict(&self, x: &[T]) -> Vec<T> {
+        self.forward_with(x.to_vec(), &mut |p| p.data())
     }
 }
 
-impl Module for MLP {
-    fn parameters(&self) -> Vec<Value> {
+impl<T: Float> Module<T> for MLP<T> {
+    fn parameters(&self) -> Vec<Value<T>> {
         self.layers.iter().flat_map(|l| l.parameters()).collect()
     }
+
+    fn named_parameters(&self) -> Vec<(String, Value<T>)> {
+        self.layers
+            .iter()
+            .enumerate()
+            .flat_map(|(i, l)| prefix_names(format!("layers.{}", i), l.named_parameters()))
+            .collect()
+    }
+}
+
+impl<T: Float> Forward<T> for MLP<T> {
+    fn forward(&self, x: &[Value<T>]) -> Vec<Value<T>> {
+        self.forward_with(x.to_vec(), &mut Value::clone)
+    }
+}
+
+/// Builds an [`MLP`] layer by layer, e.g. tanh hidden units with a sigmoid output:
+/// `MLP::builder(2).layer(4, Activation::Tanh).layer(1, Activation::Sigmoid).build(&mut rng)`
+pub struct MlpBuilder<T: Float = f64> {
+    nin: usize,
+    /// Output size, activation and weight initialization of each layer
+    layers: Vec<(usize, Activation, Init)>,
+    _scalar: PhantomData<T>,
+}
+
+impl<T: Float> MlpBuilder<T> {
+    /// Appends a layer of `nout` neurons followed by `activation`, initialized as
+    /// recommended for that activation (see [`Init::for_activation`])
+    pub fn layer(self, nout: usize, activation: Activation) -> Self {
+        self.layer_with_init(nout, activation, Init::for_activation(activation))
+    }
+
+    /// Appends a layer whose weights are initialized by `init`
+    pub fn layer_with_init(mut self, nout: usize, activation: Activation, init: Init) -> Self {
+        self.layers.push((nout, activation, init));
+        self
+    }
+
+    /// Creates the network, drawing the initial weights from `rng`
+    pub fn build(self, rng: &mut dyn RngCore) -> MLP<T> {
+        let mut fan_in = self.nin;
+        let layers = self
+            .layers
+            .into_iter()
+            .map(|(nout, activation, init)| {
+                let layer = Layer::new(fan_in, nout, activation, &init, rng);
+                fan_in = nout;
+                layer
+            })
+            .collect();
+        let model = MLP {
+            nin: self.nin,
+            layers,
+        };
+        label_parameters(&model);
+        model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn model() -> MLP {
+        MLP::new(3, &[4, 5, 1], &mut StdRng::seed_from_u64(0))
+    }
+
+    #[test]
+    fn names_parameters_by_path() {
+        let model = model();
+        let named = model.named_parameters();
+        let names: Vec<&str> = named.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names[..5],
+            [
+                "layers.0.neurons.0.w.0",
+                "layers.0.neurons.0.w.1",
+                "layers.0.neurons.0.w.2",
+                "layers.0.neurons.0.b",
+                "layers.0.neurons.1.w.0",
+            ]
+        );
+        assert_eq!(names.len(), model.parameters().len());
+        let (name, p) = &named[16 + 3 * 5];
+        assert_eq!(name, "layers.1.neurons.3.w.0");
+        assert_eq!(p.id(), model.layers()[1].neurons[3].w[0].id());
+        // Parameters are labelled with their names
+        assert!(named.iter().all(|(name, p)| p.label() == *name));
+    }
+
+    #[test]
+    fn predict_matches_forward() {
+        let model = model();
+        let x = [0.5, -1.2, 0.8];
+        let values: Vec<Value> = x.iter().map(|&xi| Value::from(xi)).collect();
+        let forward: Vec<f64> = model.forward(&values).iter().map(Value::data).collect();
+        assert_eq!(model.predict(&x), forward);
+    }
+
+    #[test]
+    fn state_dict_round_trip() {
+        let mut model = model();
+        let state = model.state_dict();
+        for p in model.parameters() {
+            p.set_data(0.0);
+        }
+        model.load_state_dict(&state).unwrap();
+        assert_eq!(model.state_dict(), state);
+        assert!(state.values().any(|&x| x != 0.0));
+    }
+
+    #[test]
+    fn load_state_dict_rejects_mismatched_names() {
+        let mut model = model();
+        let state = model.state_dict();
+        let mut changed: StateDict = state.keys().map(|name| (name.clone(), 1.0)).collect();
+
+        changed.remove("layers.1.neurons.3.b");
+        let err = model.load_state_dict(&changed).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "missing parameters in state dict: layers.1.neurons.3.b"
+        );
+        assert_eq!(model.state_dict(), state);
+
+        changed.insert("layers.1.neurons.3.b".to_string(), 1.0);
+        changed.insert("layers.3.neurons.0.b".to_string(), 1.0);
+        let err = model.load_state_dict(&changed).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "unexpected parameters in state dict: layers.3.neurons.0.b"
+        );
+        assert_eq!(model.state_dict(), state);
+    }
 }
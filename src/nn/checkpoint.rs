@@ -0,0 +1,376 @@
+//! Saving and loading [`MLP`] checkpoints.
+//!
+//! A checkpoint holds the architecture (input size, and the width and activation of
+//! every layer) together with all weights and biases, stored as `f64` whatever the
+//! network's scalar type. It can be written as human-readable JSON or in a compact
+//! little-endian binary format. Both carry a format version, and loading checks that
+//! every layer's weights fit the sizes of the layers around it.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{label_parameters, Activation, Layer, Neuron, MLP};
+use crate::engine::Float;
+
+/// Version written into new checkpoints; loading rejects any other version.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Leading bytes of a binary checkpoint
+const MAGIC: &[u8; 4] = b"MGRS";
+
+/// On-disk encoding of a checkpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Binary,
+}
+
+impl Format {
+    /// JSON for paths ending in `.json`, binary otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Binary,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    version: u32,
+    nin: usize,
+    layers: Vec<LayerCheckpoint>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LayerCheckpoint {
+    activation: Activation,
+    /// One row of input weights per neuron
+    weights: Vec<Vec<f64>>,
+    biases: Vec<f64>,
+}
+
+impl<T: Float> MLP<T> {
+    /// Writes the network to `path`, choosing the format from the extension
+    /// (see [`Format::from_path`])
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let bytes = match Format::from_path(path) {
+            Format::Json => self.to_json()?.into_bytes(),
+            Format::Binary => self.to_bytes(),
+        };
+        fs::write(path, bytes).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Reads a network saved with [`MLP::save`]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let model = match Format::from_path(path) {
+            Format::Json => Self::from_json(std::str::from_utf8(&bytes)?),
+            Format::Binary => Self::from_bytes(&bytes),
+        };
+        model.with_context(|| format!("invalid checkpoint {}", path.display()))
+    }
+
+    /// Fails if a parameter is NaN or infinite, which JSON cannot represent; the
+    /// binary format stores such values as they are.
+    pub fn to_json(&self) -> Result<String> {
+        let checkpoint = self.checkpoint();
+        for (i, layer) in checkpoint.layers.iter().enumerate() {
+            for (j, (w, b)) in layer.weights.iter().zip(&layer.biases).enumerate() {
+                ensure!(
+                    w.iter().chain([b]).all(|x| x.is_finite()),
+                    "neuron {} of layer {} has a non-finite parameter, which JSON cannot store",
+                    j,
+                    i
+                );
+            }
+        }
+        Ok(serde_json::to_string_pretty(&checkpoint)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let checkpoint: Checkpoint = serde_json::from_str(json)?;
+        Self::from_checkpoint(checkpoint)
+    }
+
+    /// Encodes the network as `MGRS`, then the version, input size and layer count
+    /// as `u32`s, then per layer its width (`u32`), activation tag (`u8`) and
+    /// parameter (`f64`), followed by the weights row by row and the biases (`f64`s).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let checkpoint = self.checkpoint();
+        let mut out = MAGIC.to_vec();
+        let nlayers = checkpoint.layers.len();
+        for n in [checkpoint.version, checkpoint.nin as u32, nlayers as u32] {
+            out.extend(n.to_le_bytes());
+        }
+        for layer in &checkpoint.layers {
+            let (tag, param) = activation_tag(layer.activation);
+            out.extend((layer.biases.len() as u32).to_le_bytes());
+            out.push(tag);
+            out.extend(param.to_le_bytes());
+            for x in layer.weights.iter().flatten().chain(&layer.biases) {
+                out.extend(x.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut r = ByteReader { bytes };
+        ensure!(r.take(4)? == MAGIC, "not a micrograd-rs checkpoint");
+        let version = r.u32()?;
+        // Check the version before reading further, since the layout may differ
+        check_version(version)?;
+        let nin = r.u32()? as usize;
+        ensure!(nin > 0, "network has no inputs");
+        let nlayers = r.u32()?;
+
+        let mut layers = Vec::new();
+        let mut fan_in = nin;
+        for i in 0..nlayers {
+            let nout = r.u32()? as usize;
+            ensure!(nout > 0, "layer {} has no neurons", i);
+            let activation = activation_from_tag(r.u8()?, r.f64()?)?;
+            // Check the sizes against the remaining bytes before allocating anything,
+            // so a corrupt header cannot request an enormous layer
+            let size = nout.checked_mul(fan_in + 1).and_then(|n| n.checked_mul(8));
+            ensure!(
+                size.is_some_and(|size| size <= r.bytes.len()),
+                "checkpoint is truncated"
+            );
+            let weights = (0..nout)
+                .map(|_| (0..fan_in).map(|_| r.f64()).collect())
+                .collect::<Result<_>>()?;
+            let biases = (0..nout).map(|_| r.f64()).collect::<Result<_>>()?;
+            layers.push(LayerCheckpoint {
+                activation,
+                weights,
+                biases,
+            });
+            fan_in = nout;
+        }
+        ensure!(
+            r.bytes.is_empty(),
+            "{} unexpected trailing bytes",
+            r.bytes.len()
+        );
+
+        Self::from_checkpoint(Checkpoint {
+            version,
+            nin,
+            layers,
+        })
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        let layers = self
+            .layers
+            .iter()
+            .map(|layer| LayerCheckpoint {
+                activation: layer.activation,
+                weights: layer
+                    .neurons
+                    .iter()
+                    .map(|n| n.w.iter().map(|w| w.data().to_f64()).collect())
+                    .collect(),
+                biases: layer.neurons.iter().map(|n| n.b.data().to_f64()).collect(),
+            })
+            .collect();
+        Checkpoint {
+            version: FORMAT_VERSION,
+            nin: self.nin,
+            layers,
+        }
+    }
+
+    fn from_checkpoint(checkpoint: Checkpoint) -> Result<Self> {
+        check_version(checkpoint.version)?;
+        ensure!(checkpoint.nin > 0, "network has no inputs");
+        let nin = checkpoint.nin;
+        let mut fan_in = nin;
+        let mut layers = Vec::with_capacity(checkpoint.layers.len());
+        for (i, layer) in checkpoint.layers.into_iter().enumerate() {
+            ensure!(!layer.biases.is_empty(), "layer {} has no neurons", i);
+            ensure!(
+                layer.weights.len() == layer.biases.len(),
+                "layer {} has {} weight rows but {} biases",
+                i,
+                layer.weights.len(),
+                layer.biases.len()
+            );
+            for (j, row) in layer.weights.iter().enumerate() {
+                ensure!(
+                    row.len() == fan_in,
+                    "neuron {} of layer {} has {} weights, expected {}",
+                    j,
+                    i,
+                    row.len(),
+                    fan_in
+                );
+            }
+            fan_in = layer.biases.len();
+            let neurons = layer
+                .weights
+                .into_iter()
+                .zip(layer.biases)
+                .map(|(w, b)| {
+                    let w = w.into_iter().map(T::from_f64).collect();
+                    Neuron::new(w, T::from_f64(b))
+                })
+                .collect();
+            layers.push(Layer {
+                neurons,
+                activation: layer.activation,
+            });
+        }
+        let model = MLP { nin, layers };
+        label_parameters(&model);
+        Ok(model)
+    }
+}
+
+fn check_version(version: u32) -> Result<()> {
+    ensure!(
+        version == FORMAT_VERSION,
+        "unsupported checkpoint version {} (expected {})",
+        version,
+        FORMAT_VERSION
+    );
+    Ok(())
+}
+
+/// Binary tag and parameter of an activation
+fn activation_tag(activation: Activation) -> (u8, f64) {
+    match activation {
+        Activation::Relu => (0, 0.0),
+        Activation::Tanh => (1, 0.0),
+        Activation::Sigmoid => (2, 0.0),
+        Activation::LeakyRelu(alpha) => (3, alpha),
+        Activation::Gelu => (4, 0.0),
+        Activation::Identity => (5, 0.0),
+        Activation::Softmax => (6, 0.0),
+    }
+}
+
+fn activation_from_tag(tag: u8, param: f64) -> Result<Activation> {
+    Ok(match tag {
+        0 => Activation::Relu,
+        1 => Activation::Tanh,
+        2 => Activation::Sigmoid,
+        3 => Activation::LeakyRelu(param),
+        4 => Activation::Gelu,
+        5 => Activation::Identity,
+        6 => Activation::Softmax,
+        _ => bail!("unknown activation tag {}", tag),
+    })
+}
+
+/// Reads little-endian values off the front of a byte slice
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        ensure!(self.bytes.len() >= n, "checkpoint is truncated");
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::nn::Module;
+
+    /// The bits of every named parameter, which must survive a round trip unchanged
+    fn state_bits(model: &MLP) -> Vec<(String, u64)> {
+        let state = model.state_dict();
+        state
+            .into_iter()
+            .map(|(name, x)| (name, x.to_bits()))
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_exactly() {
+        for seed in 0..20 {
+            let model = MLP::<f64>::new(3, &[8, 2], &mut StdRng::seed_from_u64(seed));
+            let from_json = MLP::<f64>::from_json(&model.to_json().unwrap()).unwrap();
+            let from_bytes = MLP::<f64>::from_bytes(&model.to_bytes()).unwrap();
+            assert_eq!(state_bits(&from_json), state_bits(&model));
+            assert_eq!(state_bits(&from_bytes), state_bits(&model));
+        }
+    }
+
+    /// A binary header declaring one layer of `nout` neurons, without any weights
+    fn header(nin: u32, nout: u32) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        for n in [FORMAT_VERSION, nin, 1, nout] {
+            bytes.extend(n.to_le_bytes());
+        }
+        bytes.push(0);
+        bytes.extend(0.0f64.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn json_rejects_non_finite_parameters() {
+        let model = MLP::<f64>::new(2, &[2, 1], &mut StdRng::seed_from_u64(0));
+        model.layers()[1].neurons[0].w[1].set_data(f64::NAN);
+        let err = model.to_json().err().unwrap();
+        assert!(
+            err.to_string().starts_with("neuron 0 of layer 1"),
+            "{}",
+            err
+        );
+
+        // The binary format stores it as it is
+        let loaded = MLP::<f64>::from_bytes(&model.to_bytes()).unwrap();
+        assert!(loaded.layers()[1].neurons[0].w[1].data().is_nan());
+    }
+
+    #[test]
+    fn round_trips_network_without_layers() {
+        let model = MLP::<f64>::builder(3).build(&mut StdRng::seed_from_u64(0));
+        let loaded = MLP::<f64>::from_bytes(&model.to_bytes()).unwrap();
+        assert_eq!((loaded.nin(), loaded.layers().len()), (3, 0));
+        let loaded = MLP::<f64>::from_json(&model.to_json().unwrap()).unwrap();
+        assert_eq!((loaded.nin(), loaded.layers().len()), (3, 0));
+    }
+
+    #[test]
+    fn rejects_empty_shapes() {
+        let err = MLP::<f64>::from_bytes(&header(0, 1)).err().unwrap();
+        assert_eq!(err.to_string(), "network has no inputs");
+        let err = MLP::<f64>::from_bytes(&header(2, 0)).err().unwrap();
+        assert_eq!(err.to_string(), "layer 0 has no neurons");
+    }
+
+    #[test]
+    fn rejects_layer_larger_than_file() {
+        let err = MLP::<f64>::from_bytes(&header(2, u32::MAX)).err().unwrap();
+        assert_eq!(err.to_string(), "checkpoint is truncated");
+    }
+}
@@ -0,0 +1,230 @@
+//! Weight initialization schemes.
+//!
+//! An [`Init`] fills the `fan_out × fan_in` weight matrix of a layer, drawing from the
+//! random number generator it is given. Passing a seeded generator such as
+//! `StdRng::seed_from_u64(42)` makes the initial network reproducible.
+
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use rand::{Rng, RngCore};
+
+use super::Activation;
+
+/// Computes one weight from the layer's `(fan_in, fan_out)` and a random generator
+pub type InitFn = Rc<dyn Fn(usize, usize, &mut dyn RngCore) -> f64>;
+
+/// How the weights of a layer are initialized.
+#[derive(Clone)]
+pub enum Init {
+    /// Uniform on [low, high), which must not be empty
+    Uniform(f64, f64),
+    /// Normal with the given mean and standard deviation
+    Normal(f64, f64),
+    /// Glorot/Xavier uniform on ±√(6 / (fan_in + fan_out)), suited to tanh and sigmoid
+    XavierUniform,
+    /// Glorot/Xavier normal with standard deviation √(2 / (fan_in + fan_out))
+    XavierNormal,
+    /// He/Kaiming uniform on ±√(6 / fan_in), suited to ReLU-like activations
+    HeUniform,
+    /// He/Kaiming normal with standard deviation √(2 / fan_in)
+    HeNormal,
+    /// A random (semi-)orthogonal matrix scaled by the given gain: its rows are
+    /// orthonormal if the layer narrows, its columns if it widens
+    Orthogonal(f64),
+    /// Every weight set to the same value
+    Constant(f64),
+    /// Weights drawn independently by a closure
+    Custom(InitFn),
+}
+
+impl Init {
+    /// The usual choice for a layer followed by `activation`: He for ReLU-like
+    /// activations and Xavier otherwise.
+    pub fn for_activation(activation: Activation) -> Self {
+        match activation {
+            Activation::Relu | Activation::LeakyRelu(_) | Activation::Gelu => Init::HeUniform,
+            _ => Init::XavierUniform,
+        }
+    }
+
+    /// Wraps a closure computing one weight from `(fan_in, fan_out, rng)`
+    pub fn custom<F>(f: F) -> Self
+    where
+        F: Fn(usize, usize, &mut dyn RngCore) -> f64 + 'static,
+    {
+        Init::Custom(Rc::new(f))
+    }
+
+    /// Returns the row-major `fan_out × fan_in` weight matrix of a layer.
+    ///
+    /// Panics if the range of [`Init::Uniform`] is empty.
+    pub fn weights(&self, fan_in: usize, fan_out: usize, rng: &mut dyn RngCore) -> Vec<f64> {
+        let n = fan_in * fan_out;
+        let fan_avg = (fan_in + fan_out) as f64 / 2.0;
+        match self {
+            Init::Uniform(low, high) => {
+                assert!(
+                    low < high,
+                    "Init::Uniform needs low < high, got [{}, {})",
+                    low,
+                    high
+                );
+                uniform(n, *low, *high, rng)
+            }
+            Init::Normal(mean, std) => normal(n, *mean, *std, rng),
+            Init::XavierUniform => {
+                let a = (3.0 / fan_avg).sqrt();
+                uniform(n, -a, a, rng)
+            }
+            Init::XavierNormal => normal(n, 0.0, (1.0 / fan_avg).sqrt(), rng),
+            Init::HeUniform => {
+                let a = (6.0 / fan_in as f64).sqrt();
+                uniform(n, -a, a, rng)
+            }
+            Init::HeNormal => normal(n, 0.0, (2.0 / fan_in as f64).sqrt(), rng),
+            Init::Orthogonal(gain) => orthogonal(fan_in, fan_out, *gain, rng),
+            Init::Constant(c) => vec![*c; n],
+            Init::Custom(f) => (0..n).map(|_| f(fan_in, fan_out, rng)).collect(),
+        }
+    }
+}
+
+fn uniform(n: usize, low: f64, high: f64, rng: &mut dyn RngCore) -> Vec<f64> {
+    (0..n).map(|_| rng.gen_range(low..high)).collect()
+}
+
+fn normal(n: usize, mean: f64, std: f64, rng: &mut dyn RngCore) -> Vec<f64> {
+    (0..n).map(|_| mean + std * standard_normal(rng)).collect()
+}
+
+/// Draws from N(0, 1) with the Box-Muller transform
+fn standard_normal(rng: &mut dyn RngCore) -> f64 {
+    // 1 - U[0, 1) lies in (0, 1], keeping the logarithm finite
+    let u1 = 1.0 - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+/// Orthonormalizes `min(fan_in, fan_out)` Gaussian vectors of length
+/// `max(fan_in, fan_out)` with modified Gram-Schmidt, and lays them out as the rows
+/// or columns of the `fan_out × fan_in` result.
+fn orthogonal(fan_in: usize, fan_out: usize, gain: f64, rng: &mut dyn RngCore) -> Vec<f64> {
+    let (long, short) = (fan_in.max(fan_out), fan_in.min(fan_out));
+    let mut basis: Vec<Vec<f64>> = Vec::with_capacity(short);
+    while basis.len() < short {
+        let mut v = normal(long, 0.0, 1.0, rng);
+        for q in &basis {
+            let proj: f64 = v.iter().zip(q).map(|(a, b)| a * b).sum();
+            v.iter_mut().zip(q).for_each(|(a, b)| *a -= proj * b);
+        }
+        let norm = v.iter().map(|a| a * a).sum::<f64>().sqrt();
+        // Redraw in the (practically impossible) case of a dependent vector
+        if norm > 1e-10 {
+            basis.push(v.into_iter().map(|a| a / norm).collect());
+        }
+    }
+
+    let mut w = vec![0.0; fan_in * fan_out];
+    for (i, q) in basis.iter().enumerate() {
+        for (j, &x) in q.iter().enumerate() {
+            // Orthonormal rows when the layer narrows, orthonormal columns otherwise
+            let (row, col) = if fan_out <= fan_in { (i, j) } else { (j, i) };
+            w[row * fan_in + col] = gain * x;
+        }
+    }
+    w
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn rng() -> StdRng {
+        StdRng::seed_from_u64(7)
+    }
+
+    /// Dot products of every pair of rows of a row-major `rows × cols` matrix
+    fn gram(w: &[f64], rows: usize, cols: usize) -> Vec<Vec<f64>> {
+        let row = |i: usize| &w[i * cols..(i + 1) * cols];
+        (0..rows)
+            .map(|i| {
+                (0..rows)
+                    .map(|j| row(i).iter().zip(row(j)).map(|(a, b)| a * b).sum())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn transpose(w: &[f64], rows: usize, cols: usize) -> Vec<f64> {
+        (0..cols)
+            .flat_map(|j| (0..rows).map(move |i| w[i * cols + j]))
+            .collect()
+    }
+
+    fn assert_scaled_identity(gram: &[Vec<f64>], scale: f64) {
+        for (i, row) in gram.iter().enumerate() {
+            for (j, &x) in row.iter().enumerate() {
+                let expected = if i == j { scale } else { 0.0 };
+                assert!((x - expected).abs() < 1e-12, "{:?}", gram);
+            }
+        }
+    }
+
+    #[test]
+    fn orthogonal_rows_or_columns() {
+        // Narrowing 5 → 3: the rows are orthogonal with norm equal to the gain
+        let w = Init::Orthogonal(2.0).weights(5, 3, &mut rng());
+        assert_scaled_identity(&gram(&w, 3, 5), 4.0);
+
+        // Widening 3 → 5: the columns are
+        let w = Init::Orthogonal(2.0).weights(3, 5, &mut rng());
+        assert_scaled_identity(&gram(&transpose(&w, 5, 3), 3, 5), 4.0);
+    }
+
+    #[test]
+    fn uniform_bounds() {
+        let (fan_in, fan_out) = (6, 4);
+        let xavier = (6.0 / (fan_in + fan_out) as f64).sqrt();
+        let he = (6.0 / fan_in as f64).sqrt();
+        for (init, bound) in [(Init::XavierUniform, xavier), (Init::HeUniform, he)] {
+            let w = init.weights(fan_in, fan_out, &mut rng());
+            assert_eq!(w.len(), fan_in * fan_out);
+            assert!(w.iter().all(|x| x.abs() < bound), "{:?}", w);
+            // The draws should spread over most of the range
+            assert!(w.iter().any(|x| x.abs() > bound / 2.0), "{:?}", w);
+        }
+    }
+
+    #[test]
+    fn seeded_weights_are_reproducible() {
+        let inits = [
+            Init::Uniform(-1.0, 1.0),
+            Init::Normal(0.5, 2.0),
+            Init::XavierUniform,
+            Init::XavierNormal,
+            Init::HeUniform,
+            Init::HeNormal,
+            Init::Orthogonal(1.0),
+            Init::custom(|_, _, rng| rng.gen()),
+        ];
+        for init in inits {
+            let bits = |w: Vec<f64>| w.into_iter().map(f64::to_bits).collect::<Vec<_>>();
+            let first = bits(init.weights(3, 4, &mut rng()));
+            assert_eq!(bits(init.weights(3, 4, &mut rng())), first);
+            assert_ne!(
+                bits(init.weights(3, 4, &mut StdRng::seed_from_u64(8))),
+                first
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Init::Uniform needs low < high, got [1, 1)")]
+    fn rejects_empty_uniform_range() {
+        Init::Uniform(1.0, 1.0).weights(2, 2, &mut rng());
+    }
+}
@@ -0,0 +1,198 @@
+use std::cell::RefCell;
+
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+
+use super::{label_parameters, Forward, Module};
+use crate::engine::{Float, Value};
+
+/// Randomly zeroes inputs during training to regularize the layers around it.
+///
+/// Each input is dropped with probability `p` and the survivors are scaled by
+/// 1 / (1 - p), so the expected output matches evaluation mode, where inputs pass
+/// through unchanged. A new `Dropout` starts in training mode; switch it with
+/// [`Module::set_training`].
+pub struct Dropout {
+    p: f64,
+    training: bool,
+    /// Interior mutability lets `forward` draw masks through `&self`
+    rng: RefCell<StdRng>,
+}
+
+impl Dropout {
+    /// Creates a dropout module whose masks come from a generator seeded from `rng`
+    pub fn new(p: f64, rng: &mut dyn RngCore) -> Self {
+        assert!(
+            (0.0..1.0).contains(&p),
+            "dropout probability must be in [0, 1)"
+        );
+        Self {
+            p,
+            training: true,
+            rng: RefCell::new(StdRng::seed_from_u64(rng.next_u64())),
+        }
+    }
+
+    pub fn is_training(&self) -> bool {
+        self.training
+    }
+}
+
+impl<T: Float> Module<T> for Dropout {
+    fn parameters(&self) -> Vec<Value<T>> {
+        Vec::new()
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+impl<T: Float> Forward<T> for Dropout {
+    fn forward(&self, x: &[Value<T>]) -> Vec<Value<T>> {
+        if !self.training || self.p == 0.0 {
+            return x.to_vec();
+        }
+        let scale = T::from_f64(1.0 / (1.0 - self.p));
+        let mut rng = self.rng.borrow_mut();
+        x.iter()
+            .map(|xi| {
+                if rng.gen::<f64>() < self.p {
+                    Value::from(T::zero())
+                } else {
+                    xi * scale
+                }
+            })
+            .collect()
+    }
+}
+
+/// Layer normalization: standardizes the inputs to zero mean and unit variance across
+/// features, then applies a learned per-feature scale `gamma` and shift `beta`.
+pub struct LayerNorm<T: Float = f64> {
+    gamma: Vec<Value<T>>,
+    beta: Vec<Value<T>>,
+    eps: T,
+}
+
+impl<T: Float> LayerNorm<T> {
+    /// Normalizes `size` features, starting from the identity scale and no shift
+    pub fn new(size: usize) -> Self {
+        let norm = Self {
+            gamma: (0..size)
+                .map(|_| Value::new(T::one(), None, "gamma".to_string(), None))
+                .collect(),
+            beta: (0..size)
+                .map(|_| Value::new(T::zero(), None, "beta".to_string(), None))
+                .collect(),
+            eps: T::from_f64(1e-5),
+        };
+        label_parameters(&norm);
+        norm
+    }
+
+    /// Constant added to the variance to avoid dividing by zero (default 1e-5)
+    pub fn eps(mut self, eps: T) -> Self {
+        self.eps = eps;
+        self
+    }
+}
+
+impl<T: Float> Module<T> for LayerNorm<T> {
+    fn parameters(&self) -> Vec<Value<T>> {
+        self.gamma.iter().chain(&self.beta).cloned().collect()
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value<T>)> {
+        let gamma = self
+            .gamma
+            .iter()
+            .enumerate()
+            .map(|(i, g)| (format!("gamma.{}", i), g));
+        let beta = self
+            .beta
+            .iter()
+            .enumerate()
+            .map(|(i, b)| (format!("beta.{}", i), b));
+        gamma
+            .chain(beta)
+            .map(|(name, p)| (name, p.clone()))
+            .collect()
+    }
+}
+
+impl<T: Float> Forward<T> for LayerNorm<T> {
+    fn forward(&self, x: &[Value<T>]) -> Vec<Value<T>> {
+        assert_eq!(
+            x.len(),
+            self.gamma.len(),
+            "LayerNorm over {} features got {} inputs",
+            self.gamma.len(),
+            x.len()
+        );
+        let n = T::from_f64(x.len() as f64);
+        let mean = Value::sum(x) / n;
+        let centered: Vec<Value<T>> = x.iter().map(|xi| xi - &mean).collect();
+        let squares: Vec<Value<T>> = centered.iter().map(|c| c * c).collect();
+        let inv_std = (Value::sum(&squares) / n + self.eps).pow(T::from_f64(-0.5));
+        centered
+            .iter()
+            .zip(self.gamma.iter().zip(&self.beta))
+            .map(|(c, (g, b))| c * &inv_std * g + b)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(data: &[f64]) -> Vec<Value> {
+        data.iter().map(|&x| Value::from(x)).collect()
+    }
+
+    #[test]
+    fn dropout_masks_and_scales_in_training() {
+        let mut dropout = Dropout::new(0.25, &mut StdRng::seed_from_u64(0));
+        let out: Vec<f64> = dropout
+            .forward(&inputs(&[1.0; 200]))
+            .iter()
+            .map(Value::data)
+            .collect();
+
+        // The mask is drawn from a generator seeded from the one passed to `new`
+        let mut rng = StdRng::seed_from_u64(StdRng::seed_from_u64(0).next_u64());
+        let expected: Vec<f64> = (0..200)
+            .map(|_| {
+                if rng.gen::<f64>() < 0.25 {
+                    0.0
+                } else {
+                    1.0 / 0.75
+                }
+            })
+            .collect();
+        assert_eq!(out, expected);
+        let dropped = out.iter().filter(|&&x| x == 0.0).count();
+        assert!((30..70).contains(&dropped), "{} of 200 dropped", dropped);
+
+        // Evaluation mode passes the inputs through
+        Module::<f64>::set_training(&mut dropout, false);
+        let x = inputs(&[0.5, -2.0]);
+        let out = dropout.forward(&x);
+        assert!(out.iter().zip(&x).all(|(o, x)| o.id() == x.id()));
+    }
+
+    #[test]
+    fn layer_norm_standardizes() {
+        let norm = LayerNorm::new(5);
+        let out: Vec<f64> = norm
+            .forward(&inputs(&[1.0, 2.0, 3.0, 4.0, 10.0]))
+            .iter()
+            .map(Value::data)
+            .collect();
+        let mean = out.iter().sum::<f64>() / 5.0;
+        let var = out.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 5.0;
+        assert!(mean.abs() < 1e-12, "mean {}", mean);
+        assert!((var - 1.0).abs() < 1e-5, "variance {}", var);
+    }
+}
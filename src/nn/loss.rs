@@ -0,0 +1,164 @@
+//! Loss functions comparing predictions against targets.
+//!
+//! Every loss takes equally long slices of predictions and targets, computes one term
+//! per element, and combines the terms according to a [`Reduction`]. The losses are
+//! generic over [`Scalar`], so they accept `Value` slices as well as tape variables.
+//!
+//! The losses over class distributions (cross-entropy, NLL and KL divergence) are the
+//! exception: their slices hold the classes of one sample, whose terms always add up
+//! to that sample's loss. Combine the losses of a batch with [`Reduction::reduce`].
+
+use crate::engine::Float;
+use crate::scalar::Scalar;
+
+/// How the per-element terms of a loss are combined into one scalar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reduction {
+    /// Average of the terms
+    #[default]
+    Mean,
+    /// Sum of the terms
+    Sum,
+}
+
+impl Reduction {
+    /// Combines loss terms, e.g. the per-sample losses of a batch
+    pub fn reduce<T: Float, S: Scalar<T>>(self, terms: &[S]) -> S {
+        let total = S::sum(terms);
+        match self {
+            Reduction::Mean => total / T::from_f64(terms.len() as f64),
+            Reduction::Sum => total,
+        }
+    }
+}
+
+/// Mean squared error: (p - t)²
+pub fn mse<T: Float, S: Scalar<T>>(pred: &[S], target: &[S], reduction: Reduction) -> S {
+    reduce_pairs(pred, target, reduction, |p, t| {
+        let d = p - t;
+        d.clone() * d
+    })
+}
+
+/// Mean absolute error: |p - t|
+pub fn mae<T: Float, S: Scalar<T>>(pred: &[S], target: &[S], reduction: Reduction) -> S {
+    reduce_pairs(pred, target, reduction, |p, t| (p - t).abs())
+}
+
+/// Huber loss: quadratic for errors up to `delta` and linear beyond, so that outliers
+/// contribute a bounded gradient. ½d² if |d| ≤ δ, else δ(|d| - ½δ), with d = p - t.
+pub fn huber<T: Float, S: Scalar<T>>(
+    pred: &[S],
+    target: &[S],
+    delta: T,
+    reduction: Reduction,
+) -> S {
+    let half = T::from_f64(0.5);
+    reduce_pairs(pred, target, reduction, |p, t| {
+        let d = p - t;
+        if d.value().abs() <= delta {
+            d.clone() * d * half
+        } else {
+            (d.abs() - delta * half) * delta
+        }
+    })
+}
+
+/// Binary cross-entropy on probabilities p ∈ (0, 1): -(t ln p + (1 - t) ln(1 - p)).
+/// Prefer [`bce_with_logits`] when the predictions come straight from a linear layer.
+pub fn bce<T: Float, S: Scalar<T>>(pred: &[S], target: &[S], reduction: Reduction) -> S {
+    reduce_pairs(pred, target, reduction, |p, t| {
+        let one_minus_t = -t.clone() + T::one();
+        let one_minus_p = -p.clone() + T::one();
+        -(t * p.ln() + one_minus_t * one_minus_p.ln())
+    })
+}
+
+/// Binary cross-entropy on logits x, i.e. on p = σ(x), computed stably as
+/// softplus(x) - t·x without ever evaluating ln σ(x).
+pub fn bce_with_logits<T: Float, S: Scalar<T>>(
+    logits: &[S],
+    target: &[S],
+    reduction: Reduction,
+) -> S {
+    reduce_pairs(logits, target, reduction, |x, t| x.softplus() - x * t)
+}
+
+/// Categorical cross-entropy between the softmax of `logits` and a target distribution
+/// (one-hot or soft labels): -Σ tᵢ log softmax(x)ᵢ.
+pub fn cross_entropy<T: Float, S: Scalar<T>>(logits: &[S], target: &[S]) -> S {
+    nll(&S::log_softmax(logits), target)
+}
+
+/// Negative log-likelihood of log-probabilities, e.g. the output of
+/// `log_softmax`, under a target distribution: -Σ tᵢ log pᵢ.
+pub fn nll<T: Float, S: Scalar<T>>(log_probs: &[S], target: &[S]) -> S {
+    reduce_pairs(log_probs, target, Reduction::Sum, |lp, t| -(t * lp))
+}
+
+/// Hinge (SVM) loss for targets in {-1, 1}: max(0, 1 - t·p)
+pub fn hinge<T: Float, S: Scalar<T>>(pred: &[S], target: &[S], reduction: Reduction) -> S {
+    reduce_pairs(pred, target, reduction, |p, t| (-(p * t) + T::one()).relu())
+}
+
+/// Kullback-Leibler divergence KL(t ‖ p) = Σ tᵢ (ln tᵢ - log pᵢ), taking the
+/// predictions as log-probabilities. Terms with tᵢ = 0 contribute nothing.
+pub fn kl_div<T: Float, S: Scalar<T>>(log_probs: &[S], target: &[S]) -> S {
+    reduce_pairs(log_probs, target, Reduction::Sum, |lp, t| {
+        if t.value() > T::zero() {
+            t.clone() * (t.ln() - lp)
+        } else {
+            S::constant(T::zero())
+        }
+    })
+}
+
+/// Computes `term(pred[i], target[i])` for every element and reduces the results.
+fn reduce_pairs<T, S, F>(pred: &[S], target: &[S], reduction: Reduction, mut term: F) -> S
+where
+    T: Float,
+    S: Scalar<T>,
+    F: FnMut(S, S) -> S,
+{
+    assert_eq!(
+        pred.len(),
+        target.len(),
+        "predictions and targets must have the same length"
+    );
+    let terms: Vec<S> = pred
+        .iter()
+        .zip(target)
+        .map(|(p, t)| term(p.clone(), t.clone()))
+        .collect();
+    reduction.reduce(&terms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Value;
+
+    fn values(data: &[f64]) -> Vec<Value> {
+        data.iter().map(|&x| Value::from(x)).collect()
+    }
+
+    #[test]
+    fn cross_entropy_is_per_sample() {
+        // -ln softmax(x)₁ does not depend on how many classes there are
+        let logits = values(&[0.3, -1.2, 2.0, 0.5]);
+        let loss = cross_entropy(&logits, &values(&[0.0, 1.0, 0.0, 0.0]));
+        let lse = [0.3f64, -1.2, 2.0, 0.5]
+            .iter()
+            .map(|x| x.exp())
+            .sum::<f64>()
+            .ln();
+        assert!((loss.data() - (lse + 1.2)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn reduce_averages_sample_losses() {
+        let losses = values(&[1.0, 2.0, 6.0]);
+        assert_eq!(Reduction::Mean.reduce(&losses).data(), 3.0);
+        assert_eq!(Reduction::Sum.reduce(&losses).data(), 9.0);
+    }
+}
@@ -0,0 +1,117 @@
+use super::{prefix_names, Forward, Module};
+use crate::engine::{Float, Value};
+
+/// Chains modules, feeding the output of each into the next.
+///
+/// Parameters are named by the position of their module, e.g. `0.neurons.2.w.1`
+/// for a weight of the first layer.
+pub struct Sequential<T: Float = f64> {
+    modules: Vec<Box<dyn Forward<T>>>,
+}
+
+impl<T: Float> Sequential<T> {
+    pub fn new() -> Self {
+        Self {
+            modules: Vec::new(),
+        }
+    }
+
+    /// Appends a module, builder style
+    pub fn with<M: Forward<T> + 'static>(mut self, module: M) -> Self {
+        self.push(module);
+        self
+    }
+
+    /// Appends a module, labelling its parameters with their names in the sequence
+    pub fn push<M: Forward<T> + 'static>(&mut self, module: M) {
+        let index = self.modules.len().to_string();
+        for (name, p) in prefix_names(index, module.named_parameters()) {
+            p.set_label(name);
+        }
+        self.modules.push(Box::new(module));
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    pub fn modules(&self) -> &[Box<dyn Forward<T>>] {
+        &self.modules
+    }
+}
+
+impl<T: Float> Default for Sequential<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float> Module<T> for Sequential<T> {
+    fn parameters(&self) -> Vec<Value<T>> {
+        self.modules.iter().flat_map(|m| m.parameters()).collect()
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value<T>)> {
+        self.modules
+            .iter()
+            .enumerate()
+            .flat_map(|(i, m)| prefix_names(i.to_string(), m.named_parameters()))
+            .collect()
+    }
+
+    fn set_training(&mut self, training: bool) {
+        for m in &mut self.modules {
+            m.set_training(training);
+        }
+    }
+}
+
+impl<T: Float> Forward<T> for Sequential<T> {
+    fn forward(&self, x: &[Value<T>]) -> Vec<Value<T>> {
+        self.modules
+            .iter()
+            .fold(x.to_vec(), |x, module| module.forward(&x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::nn::init::Init;
+    use crate::nn::{Activation, Layer, LayerNorm};
+
+    #[test]
+    fn names_parameters_by_position() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut linear = |nin, nout| {
+            Layer::<f64>::new(
+                nin,
+                nout,
+                Activation::Identity,
+                &Init::XavierNormal,
+                &mut rng,
+            )
+        };
+        let (hidden, output) = (linear(3, 4), linear(4, 2));
+        let sequential = Sequential::new()
+            .with(hidden)
+            .with(Activation::Tanh)
+            .with(LayerNorm::new(4))
+            .with(output);
+
+        let named = sequential.named_parameters();
+        let names: Vec<&str> = named.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names.len(), 16 + 8 + 10);
+        assert_eq!(names[2 * 4 + 1], "0.neurons.2.w.1");
+        assert_eq!(names[16], "2.gamma.0");
+        assert_eq!(names[16 + 8], "3.neurons.0.w.0");
+        assert!(named.iter().all(|(name, p)| p.label() == *name));
+    }
+}
@@ -0,0 +1,401 @@
+//! Operations that can produce a node in the computation graph.
+//! Each operation knows how to compute its output from its inputs and the local
+//! derivative of that output with respect to every input; the engine combines the
+//! latter with the upstream gradient during backprop.
+
+use std::fmt::{Debug, Display};
+#[cfg(not(feature = "sync"))]
+use std::rc::Rc;
+#[cfg(feature = "sync")]
+use std::sync::Arc;
+
+use crate::engine::Value;
+use crate::float::Float;
+
+/// A user-defined differentiable function of N scalar inputs.
+///
+/// Implement this to plug domain-specific operations into the graph without
+/// touching the engine, then apply it with [`crate::Value::custom`].
+pub trait CustomOp<T: Float = f64> {
+    /// Name shown in node labels and in the visualizers
+    fn name(&self) -> &str;
+
+    /// Computes the output from the input data
+    fn forward(&self, inputs: &[T]) -> T;
+
+    /// Returns ∂out/∂input for each input, given the input data and the output
+    /// computed by [`CustomOp::forward`]. Must return exactly one entry per input.
+    fn backward(&self, inputs: &[T], out: T) -> Vec<T>;
+
+    /// Graph version of [`CustomOp::backward`], used by
+    /// [`Value::backward_create_graph`]. The default wraps the numeric local gradients
+    /// as constants, so higher-order derivatives through the op are treated as zero
+    /// unless this is overridden with an expression built from `inputs` and `out`.
+    fn backward_graph(&self, inputs: &[Value<T>], out: &Value<T>) -> Vec<Value<T>> {
+        let data: Vec<T> = inputs.iter().map(Value::data).collect();
+        self.backward(&data, out.data())
+            .into_iter()
+            .map(Value::from)
+            .collect()
+    }
+}
+
+/// Shared handle to a custom op, as stored in the graph. With the `sync` feature it
+/// is an `Arc` and the op must be `Send + Sync`, so that graphs can cross threads.
+#[cfg(not(feature = "sync"))]
+pub type CustomOpRef<T = f64> = Rc<dyn CustomOp<T>>;
+#[cfg(feature = "sync")]
+pub type CustomOpRef<T = f64> = Arc<dyn CustomOp<T> + Send + Sync>;
+
+/// The operation that produced a node in the computation graph.
+#[derive(Clone)]
+pub enum Op<T: Float = f64> {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Pow(T),
+    Tanh,
+    Relu,
+    LeakyRelu(T),
+    Elu,
+    Exp,
+    Ln,
+    Log2,
+    Sigmoid,
+    Softplus,
+    Gelu,
+    Silu,
+    Abs,
+    Sqrt,
+    Sin,
+    Cos,
+    /// Sum of all inputs, fused into a single node. Takes at least one input.
+    Sum,
+    /// Dot product of the first half of the inputs with the second half.
+    /// Takes at least one pair of inputs.
+    Dot,
+    /// ln Σ e^xᵢ over all inputs, fused into a single node. Takes at least one input.
+    LogSumExp,
+    Custom(CustomOpRef<T>),
+}
+
+impl<T: Float> Op<T> {
+    /// Computes the output of this operation from the input data.
+    pub fn forward(&self, inputs: &[T]) -> T {
+        // Variadic ops read all their inputs, which may be empty for a custom op
+        let x = match self {
+            Op::Sum => return inputs.iter().fold(T::zero(), |acc, &x| acc + x),
+            Op::Dot => {
+                let (a, b) = inputs.split_at(inputs.len() / 2);
+                return a.iter().zip(b).fold(T::zero(), |acc, (&x, &y)| acc + x * y);
+            }
+            Op::LogSumExp => return logsumexp(inputs),
+            Op::Custom(op) => return op.forward(inputs),
+            _ => inputs[0],
+        };
+        match self {
+            Op::Add => x + inputs[1],
+            Op::Sub => x - inputs[1],
+            Op::Mul => x * inputs[1],
+            Op::Div => x / inputs[1],
+            Op::Neg => -x,
+            Op::Pow(exponent) => x.powf(*exponent),
+            Op::Tanh => x.tanh(),
+            // ReLU(x) = max(0, x)
+            Op::Relu => {
+                if x > T::zero() {
+                    x
+                } else {
+                    T::zero()
+                }
+            }
+            Op::LeakyRelu(alpha) => {
+                if x > T::zero() {
+                    x
+                } else {
+                    *alpha * x
+                }
+            }
+            // ELU(x) = x if x > 0, else e^x - 1
+            Op::Elu => {
+                if x > T::zero() {
+                    x
+                } else {
+                    x.exp_m1()
+                }
+            }
+            Op::Exp => x.exp(),
+            Op::Ln => x.ln(),
+            Op::Log2 => x.log2(),
+            Op::Sigmoid => sigmoid(x),
+            // softplus(x) = ln(1 + e^x), evaluated as max(x, 0) + ln(1 + e^-|x|) to avoid overflow
+            Op::Softplus => x.max(T::zero()) + (-x.abs()).exp().ln_1p(),
+            // GELU(x) = 0.5x(1 + tanh(√(2/π)(x + 0.044715x³)))
+            Op::Gelu => T::from_f64(0.5) * x * (T::one() + gelu_inner(x).tanh()),
+            // SiLU(x) = x·σ(x)
+            Op::Silu => x * sigmoid(x),
+            Op::Abs => x.abs(),
+            Op::Sqrt => x.sqrt(),
+            Op::Sin => x.sin(),
+            Op::Cos => x.cos(),
+            Op::Sum | Op::Dot | Op::LogSumExp | Op::Custom(_) => unreachable!(),
+        }
+    }
+
+    /// Returns ∂out/∂input for each input, given the input data and the output.
+    /// The backward pass scales these by the upstream gradient (chain rule).
+    pub fn local_grads(&self, inputs: &[T], out: T) -> Vec<T> {
+        let x = match self {
+            Op::Sum => return vec![T::one(); inputs.len()],
+            // ∂(a·b)/∂a = b and ∂(a·b)/∂b = a, computed for the whole vector at once
+            Op::Dot => {
+                let (a, b) = inputs.split_at(inputs.len() / 2);
+                return b.iter().chain(a).copied().collect();
+            }
+            // ∂lse/∂xᵢ = e^xᵢ / Σ e^xⱼ = softmax(x)ᵢ, and out is already ln Σ e^xⱼ
+            Op::LogSumExp => return inputs.iter().map(|&x| (x - out).exp()).collect(),
+            Op::Custom(op) => return op.backward(inputs, out),
+            _ => inputs[0],
+        };
+        match self {
+            // Addition distributes the gradient unchanged
+            Op::Add => vec![T::one(), T::one()],
+            Op::Sub => vec![T::one(), -T::one()],
+            // Each factor's gradient is scaled by the other factor
+            Op::Mul => vec![inputs[1], x],
+            Op::Div => {
+                let y = inputs[1];
+                vec![T::one() / y, -x / (y * y)]
+            }
+            Op::Neg => vec![-T::one()],
+            // Power rule: ∂(x^n)/∂x = n * x^(n-1)
+            Op::Pow(exponent) => vec![*exponent * x.powf(*exponent - T::one())],
+            // ∂tanh(x)/∂x = 1 - tanh²(x), and out is already tanh(x)
+            Op::Tanh => vec![T::one() - out * out],
+            // ∂ReLU(x)/∂x = 1 if x > 0, else 0
+            Op::Relu => vec![if out > T::zero() { T::one() } else { T::zero() }],
+            Op::LeakyRelu(alpha) => vec![if x > T::zero() { T::one() } else { *alpha }],
+            // ∂ELU(x)/∂x = 1 if x > 0, else e^x = ELU(x) + 1
+            Op::Elu => vec![if x > T::zero() {
+                T::one()
+            } else {
+                out + T::one()
+            }],
+            // ∂e^x/∂x = e^x
+            Op::Exp => vec![out],
+            Op::Ln => vec![T::one() / x],
+            Op::Log2 => vec![T::one() / (x * T::from_f64(std::f64::consts::LN_2))],
+            // ∂σ(x)/∂x = σ(x)(1 - σ(x))
+            Op::Sigmoid => vec![out * (T::one() - out)],
+            // ∂softplus(x)/∂x = σ(x)
+            Op::Softplus => vec![sigmoid(x)],
+            Op::Gelu => {
+                // Product rule over 0.5x and (1 + tanh(u)), with ∂u/∂x = √(2/π)(1 + 3·0.044715x²)
+                let half = T::from_f64(0.5);
+                let t = gelu_inner(x).tanh();
+                let du = T::from_f64(GELU_COEFF) * (T::one() + T::from_f64(3.0 * 0.044715) * x * x);
+                vec![half * (T::one() + t) + half * x * (T::one() - t * t) * du]
+            }
+            Op::Silu => {
+                // ∂(x·σ(x))/∂x = σ(x) + x·σ(x)(1 - σ(x))
+                let s = sigmoid(x);
+                vec![s + x * s * (T::one() - s)]
+            }
+            // sign(x), taking 0 as the subgradient at x = 0
+            Op::Abs => vec![if x > T::zero() {
+                T::one()
+            } else if x < T::zero() {
+                -T::one()
+            } else {
+                T::zero()
+            }],
+            // ∂√x/∂x = 1/(2√x)
+            Op::Sqrt => vec![T::from_f64(0.5) / out],
+            Op::Sin => vec![x.cos()],
+            Op::Cos => vec![-x.sin()],
+            Op::Sum | Op::Dot | Op::LogSumExp | Op::Custom(_) => unreachable!(),
+        }
+    }
+
+    /// Graph version of [`Op::local_grads`]: builds each ∂out/∂input as a Value
+    /// expression of the inputs and output, so that it can itself be differentiated.
+    pub fn local_grads_graph(&self, inputs: &[Value<T>], out: &Value<T>) -> Vec<Value<T>> {
+        let one = || Value::from(T::one());
+        let x = match self {
+            Op::Sum => return inputs.iter().map(|_| one()).collect(),
+            Op::Dot => {
+                let (a, b) = inputs.split_at(inputs.len() / 2);
+                return b.iter().chain(a).cloned().collect();
+            }
+            Op::LogSumExp => return inputs.iter().map(|x| (x - out).exp()).collect(),
+            Op::Custom(op) => return op.backward_graph(inputs, out),
+            _ => &inputs[0],
+        };
+        match self {
+            Op::Add => vec![one(), one()],
+            Op::Sub => vec![one(), -one()],
+            Op::Mul => vec![inputs[1].clone(), x.clone()],
+            Op::Div => {
+                let y = &inputs[1];
+                vec![one() / y, -(x / (y * y))]
+            }
+            Op::Neg => vec![-one()],
+            Op::Pow(exponent) => vec![x.pow(*exponent - T::one()) * *exponent],
+            Op::Tanh => vec![one() - out * out],
+            // Piecewise linear ops have a locally constant slope
+            Op::Relu | Op::LeakyRelu(_) | Op::Abs => {
+                vec![Value::from(self.local_grads(&[x.data()], out.data())[0])]
+            }
+            Op::Elu => {
+                if x.data() > T::zero() {
+                    vec![one()]
+                } else {
+                    vec![out + T::one()]
+                }
+            }
+            Op::Exp => vec![out.clone()],
+            Op::Ln => vec![one() / x],
+            Op::Log2 => vec![one() / (x * T::from_f64(std::f64::consts::LN_2))],
+            Op::Sigmoid => vec![out * (one() - out)],
+            Op::Softplus => vec![x.sigmoid()],
+            Op::Gelu => {
+                let half = T::from_f64(0.5);
+                let coeff = T::from_f64(GELU_COEFF);
+                let t = ((x + x.pow(T::from_f64(3.0)) * T::from_f64(0.044715)) * coeff).tanh();
+                let du = (x * x * T::from_f64(3.0 * 0.044715) + T::one()) * coeff;
+                vec![(&t + T::one()) * half + x * (one() - &t * &t) * du * half]
+            }
+            Op::Silu => {
+                let s = x.sigmoid();
+                vec![&s + x * &s * (one() - &s)]
+            }
+            Op::Sqrt => vec![Value::from(T::from_f64(0.5)) / out],
+            Op::Sin => vec![x.cos()],
+            Op::Cos => vec![-x.sin()],
+            Op::Sum | Op::Dot | Op::LogSumExp | Op::Custom(_) => unreachable!(),
+        }
+    }
+
+    /// Builds the auto-generated label of a node from its input labels.
+    pub(crate) fn label(&self, inputs: &[String]) -> String {
+        match self {
+            Op::Add | Op::Sub | Op::Mul | Op::Div => {
+                format!("({}_{}_{}", inputs[0], self, inputs[1])
+            }
+            Op::Pow(exponent) => format!("{}^{}", inputs[0], exponent),
+            _ => format!("{}({})", self, inputs.join(", ")),
+        }
+    }
+}
+
+impl<T: Float> Display for Op<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Neg => "neg",
+            Op::Pow(_) => "pow",
+            Op::Tanh => "tanh",
+            Op::Relu => "relu",
+            Op::LeakyRelu(_) => "leaky_relu",
+            Op::Elu => "elu",
+            Op::Exp => "exp",
+            Op::Ln => "ln",
+            Op::Log2 => "log2",
+            Op::Sigmoid => "sigmoid",
+            Op::Softplus => "softplus",
+            Op::Gelu => "gelu",
+            Op::Silu => "silu",
+            Op::Abs => "abs",
+            Op::Sqrt => "sqrt",
+            Op::Sin => "sin",
+            Op::Cos => "cos",
+            Op::Sum => "sum",
+            Op::Dot => "dot",
+            Op::LogSumExp => "logsumexp",
+            Op::Custom(op) => op.name(),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl<T: Float> Debug for Op<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Op({})", self)
+    }
+}
+
+/// √(2/π), the scale applied inside the GELU tanh approximation.
+const GELU_COEFF: f64 = 0.797_884_560_802_865_4;
+
+/// Argument of tanh in the GELU approximation: √(2/π)(x + 0.044715x³)
+fn gelu_inner<T: Float>(x: T) -> T {
+    T::from_f64(GELU_COEFF) * (x + T::from_f64(0.044715) * x * x * x)
+}
+
+/// ln Σ e^xᵢ, evaluated as m + ln Σ e^(xᵢ - m) with m = max xᵢ so that no term
+/// overflows. If m is infinite, so is the result, and it is returned directly rather
+/// than producing NaN from ∞ - ∞.
+pub(crate) fn logsumexp<T: Float>(inputs: &[T]) -> T {
+    let m = inputs
+        .iter()
+        .copied()
+        .reduce(T::max)
+        .unwrap_or(T::from_f64(f64::NEG_INFINITY));
+    if m.to_f64().is_infinite() {
+        return m;
+    }
+    m + inputs
+        .iter()
+        .fold(T::zero(), |acc, &x| acc + (x - m).exp())
+        .ln()
+}
+
+/// Numerically stable logistic function that never exponentiates a large positive number.
+fn sigmoid<T: Float>(x: T) -> T {
+    if x >= T::zero() {
+        T::one() / (T::one() + (-x).exp())
+    } else {
+        let e = x.exp();
+        e / (T::one() + e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A custom op without inputs that always produces the same number
+    struct Constant(f64);
+
+    impl CustomOp for Constant {
+        fn name(&self) -> &str {
+            "constant"
+        }
+
+        fn forward(&self, _inputs: &[f64]) -> f64 {
+            self.0
+        }
+
+        fn backward(&self, _inputs: &[f64], _out: f64) -> Vec<f64> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn custom_op_without_inputs() {
+        #[cfg(not(feature = "sync"))]
+        let op: CustomOpRef = Rc::new(Constant(2.5));
+        #[cfg(feature = "sync")]
+        let op: CustomOpRef = Arc::new(Constant(2.5));
+
+        let c = Value::custom(op, &[]);
+        assert_eq!(c.data(), 2.5);
+        c.backward();
+        assert!(c.backward_create_graph().wrt(&[]).is_empty());
+    }
+}
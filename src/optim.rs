@@ -0,0 +1,695 @@
+//! Gradient-based optimizers.
+//!
+//! An optimizer is created from a model's [`Module::parameters`](crate::nn::Module) and
+//! keeps any per-parameter state (momentum buffers, running averages) next to them.
+//! A training step calls `zero_grad`, builds the loss, runs `backward` and then `step`.
+//! The learning rate can be varied over training with a schedule from [`lr_scheduler`].
+//!
+//! Parameters can be split into [`ParamGroup`]s with their own learning rate and
+//! weight decay, e.g. to fine-tune the early layers of a pretrained network more
+//! gently than its head. Parameters that do not require a gradient (see
+//! [`Value::set_requires_grad`]) are never updated.
+
+pub mod lr_scheduler;
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::engine::{Float, Value};
+
+/// Updates a set of parameters from their gradients.
+pub trait Optimizer<T: Float = f64> {
+    /// The parameters updated by [`Optimizer::step`]
+    fn parameters(&self) -> &[Value<T>];
+
+    fn learning_rate(&self) -> T;
+
+    /// Changes the learning rate used by subsequent steps, e.g. from a schedule.
+    /// Parameter groups with their own rate scale it by the ratio of `lr` to the rate
+    /// the optimizer was created with.
+    fn set_learning_rate(&mut self, lr: T);
+
+    /// Adds parameters updated with the group's learning rate and weight decay.
+    /// Panics if a parameter is already in one of the optimizer's groups.
+    fn add_param_group(&mut self, group: ParamGroup<T>);
+
+    /// Applies one update to every parameter that requires a gradient
+    fn step(&mut self);
+
+    /// Resets the gradient of every parameter, ready for the next backward pass
+    fn zero_grad(&self) {
+        for p in self.parameters() {
+            p.set_grad(T::zero());
+        }
+    }
+}
+
+/// Parameters sharing a learning rate and weight decay within an optimizer. Settings
+/// that are not given fall back to the optimizer's own. A parameter listed more than
+/// once, like a weight tied between two layers, is still updated once per step.
+pub struct ParamGroup<T: Float = f64> {
+    params: Vec<Value<T>>,
+    lr: Option<T>,
+    weight_decay: Option<T>,
+}
+
+impl<T: Float> ParamGroup<T> {
+    pub fn new(params: Vec<Value<T>>) -> Self {
+        Self {
+            params,
+            lr: None,
+            weight_decay: None,
+        }
+    }
+
+    /// Learning rate of the group while the optimizer runs at the rate it was created
+    /// with. Schedules scale it by the same factor as the optimizer's rate.
+    pub fn lr(mut self, lr: T) -> Self {
+        self.lr = Some(lr);
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: T) -> Self {
+        self.weight_decay = Some(weight_decay);
+        self
+    }
+}
+
+/// The parameters of an optimizer, stored contiguously group after group so that
+/// per-parameter state can be kept in flat vectors alongside them.
+struct ParamGroups<T: Float> {
+    params: Vec<Value<T>>,
+    /// Ids of all parameters, so that none is added to two groups
+    ids: HashSet<usize>,
+    groups: Vec<GroupSettings<T>>,
+    /// The optimizer's learning rate when it was created
+    initial_lr: T,
+}
+
+struct GroupSettings<T> {
+    /// Indices of the group's parameters
+    range: Range<usize>,
+    /// The group's own learning rate, relative to the optimizer's initial one
+    lr: Option<T>,
+    weight_decay: Option<T>,
+}
+
+impl<T: Float> ParamGroups<T> {
+    /// A single group using the settings of an optimizer created with rate `lr`
+    fn new(params: Vec<Value<T>>, lr: T) -> Self {
+        let mut groups = Self {
+            params: Vec::new(),
+            ids: HashSet::new(),
+            groups: Vec::new(),
+            initial_lr: lr,
+        };
+        groups.add(ParamGroup::new(params));
+        groups
+    }
+
+    fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Appends `group`, keeping one copy of a parameter it lists several times, such
+    /// as a tied weight. Panics if one of its parameters is already in another group,
+    /// since the two groups' settings would conflict.
+    fn add(&mut self, group: ParamGroup<T>) {
+        let mut ids = HashSet::new();
+        let params: Vec<Value<T>> = group
+            .params
+            .into_iter()
+            .filter(|p| ids.insert(p.id()))
+            .collect();
+        for p in &params {
+            assert!(
+                !self.ids.contains(&p.id()),
+                "parameter '{}' appears more than once in the optimizer's parameter groups",
+                p.label()
+            );
+        }
+        self.ids.extend(ids);
+        let start = self.params.len();
+        self.params.extend(params);
+        self.groups.push(GroupSettings {
+            range: start..self.params.len(),
+            lr: group.lr,
+            weight_decay: group.weight_decay,
+        });
+    }
+
+    /// Yields the index, learning rate and weight decay of every parameter that
+    /// requires a gradient, given the optimizer's current settings. Groups with their
+    /// own rate scale it by how far the optimizer's rate has moved from its initial
+    /// one; if that was zero there is nothing to scale by, and they keep their rate.
+    fn trainable(&self, lr: T, weight_decay: T) -> impl Iterator<Item = (usize, &Value<T>, T, T)> {
+        let scale = if self.initial_lr == T::zero() {
+            T::one()
+        } else {
+            lr / self.initial_lr
+        };
+        self.groups
+            .iter()
+            .flat_map(move |group| {
+                let lr = group.lr.map_or(lr, |group_lr| group_lr * scale);
+                let weight_decay = group.weight_decay.unwrap_or(weight_decay);
+                group
+                    .range
+                    .clone()
+                    .map(move |i| (i, &self.params[i], lr, weight_decay))
+            })
+            .filter(|(_, p, _, _)| p.requires_grad())
+    }
+}
+
+/// Stochastic gradient descent with optional momentum, Nesterov momentum and L2
+/// weight decay.
+///
+/// With momentum μ the update is v ← μv + g and p ← p - lr·v, or p ← p - lr·(g + μv)
+/// with Nesterov momentum.
+pub struct Sgd<T: Float = f64> {
+    params: ParamGroups<T>,
+    lr: T,
+    momentum: T,
+    nesterov: bool,
+    weight_decay: T,
+    velocity: Vec<T>,
+}
+
+impl<T: Float> Sgd<T> {
+    pub fn new(params: Vec<Value<T>>, lr: T) -> Self {
+        let params = ParamGroups::new(params, lr);
+        let velocity = vec![T::zero(); params.len()];
+        Self {
+            params,
+            lr,
+            momentum: T::zero(),
+            nesterov: false,
+            weight_decay: T::zero(),
+            velocity,
+        }
+    }
+
+    pub fn momentum(mut self, momentum: T) -> Self {
+        self.momentum = momentum;
+        self
+    }
+
+    /// Evaluates the gradient at the look-ahead point, which usually converges faster
+    pub fn nesterov(mut self, nesterov: bool) -> Self {
+        self.nesterov = nesterov;
+        self
+    }
+
+    /// Adds `weight_decay · p` to every gradient (L2 regularization)
+    pub fn weight_decay(mut self, weight_decay: T) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+
+    /// Adds a parameter group, builder style
+    pub fn param_group(mut self, group: ParamGroup<T>) -> Self {
+        self.add_param_group(group);
+        self
+    }
+}
+
+impl<T: Float> Optimizer<T> for Sgd<T> {
+    fn parameters(&self) -> &[Value<T>] {
+        &self.params.params
+    }
+
+    fn learning_rate(&self) -> T {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: T) {
+        self.lr = lr;
+    }
+
+    fn add_param_group(&mut self, group: ParamGroup<T>) {
+        self.params.add(group);
+        self.velocity.resize(self.params.len(), T::zero());
+    }
+
+    fn step(&mut self) {
+        for (i, p, lr, weight_decay) in self.params.trainable(self.lr, self.weight_decay) {
+            let v = &mut self.velocity[i];
+            let data = p.data();
+            let mut g = p.grad() + weight_decay * data;
+            if self.momentum != T::zero() {
+                *v = self.momentum * *v + g;
+                g = if self.nesterov {
+                    g + self.momentum * *v
+                } else {
+                    *v
+                };
+            }
+            p.set_data(data - lr * g);
+        }
+    }
+}
+
+/// Adam: per-parameter step sizes from bias-corrected running averages of the
+/// gradient (m) and of its square (v): p ← p - lr·m̂/(√v̂ + ε).
+///
+/// Weight decay is added to the gradient (L2 regularization); see [`AdamW`] for the
+/// decoupled variant.
+pub struct Adam<T: Float = f64> {
+    params: ParamGroups<T>,
+    lr: T,
+    beta1: T,
+    beta2: T,
+    eps: T,
+    weight_decay: T,
+    /// Apply weight decay directly to the parameters instead of to the gradient
+    decoupled: bool,
+    t: i32,
+    m: Vec<T>,
+    v: Vec<T>,
+}
+
+impl<T: Float> Adam<T> {
+    /// Creates Adam with the usual defaults β = (0.9, 0.999) and ε = 1e-8
+    pub fn new(params: Vec<Value<T>>, lr: T) -> Self {
+        let params = ParamGroups::new(params, lr);
+        let n = params.len();
+        Self {
+            params,
+            lr,
+            beta1: T::from_f64(0.9),
+            beta2: T::from_f64(0.999),
+            eps: T::from_f64(1e-8),
+            weight_decay: T::zero(),
+            decoupled: false,
+            t: 0,
+            m: vec![T::zero(); n],
+            v: vec![T::zero(); n],
+        }
+    }
+
+    /// Decay rates of the running averages of the gradient and its square
+    pub fn betas(mut self, beta1: T, beta2: T) -> Self {
+        self.beta1 = beta1;
+        self.beta2 = beta2;
+        self
+    }
+
+    pub fn eps(mut self, eps: T) -> Self {
+        self.eps = eps;
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: T) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+
+    pub fn param_group(mut self, group: ParamGroup<T>) -> Self {
+        self.add_param_group(group);
+        self
+    }
+}
+
+impl<T: Float> Optimizer<T> for Adam<T> {
+    fn parameters(&self) -> &[Value<T>] {
+        &self.params.params
+    }
+
+    fn learning_rate(&self) -> T {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: T) {
+        self.lr = lr;
+    }
+
+    fn add_param_group(&mut self, group: ParamGroup<T>) {
+        self.params.add(group);
+        self.m.resize(self.params.len(), T::zero());
+        self.v.resize(self.params.len(), T::zero());
+    }
+
+    fn step(&mut self) {
+        self.t += 1;
+        let t = T::from_f64(self.t as f64);
+        let bias1 = T::one() - self.beta1.powf(t);
+        let bias2 = T::one() - self.beta2.powf(t);
+
+        for (i, p, lr, weight_decay) in self.params.trainable(self.lr, self.weight_decay) {
+            let (m, v) = (&mut self.m[i], &mut self.v[i]);
+            let mut data = p.data();
+            let mut g = p.grad();
+            if self.decoupled {
+                data = data - lr * weight_decay * data;
+            } else {
+                g += weight_decay * data;
+            }
+            *m = self.beta1 * *m + (T::one() - self.beta1) * g;
+            *v = self.beta2 * *v + (T::one() - self.beta2) * g * g;
+            let m_hat = *m / bias1;
+            let v_hat = *v / bias2;
+            p.set_data(data - lr * m_hat / (v_hat.sqrt() + self.eps));
+        }
+    }
+}
+
+/// Adam with decoupled weight decay: parameters shrink by lr·λ·p each step,
+/// independently of the adaptive gradient scaling. Defaults to λ = 0.01.
+pub struct AdamW<T: Float = f64>(Adam<T>);
+
+impl<T: Float> AdamW<T> {
+    pub fn new(params: Vec<Value<T>>, lr: T) -> Self {
+        let mut adam = Adam::new(params, lr).weight_decay(T::from_f64(0.01));
+        adam.decoupled = true;
+        Self(adam)
+    }
+
+    pub fn betas(self, beta1: T, beta2: T) -> Self {
+        Self(self.0.betas(beta1, beta2))
+    }
+
+    pub fn eps(self, eps: T) -> Self {
+        Self(self.0.eps(eps))
+    }
+
+    pub fn weight_decay(self, weight_decay: T) -> Self {
+        Self(self.0.weight_decay(weight_decay))
+    }
+
+    pub fn param_group(self, group: ParamGroup<T>) -> Self {
+        Self(self.0.param_group(group))
+    }
+}
+
+impl<T: Float> Optimizer<T> for AdamW<T> {
+    fn parameters(&self) -> &[Value<T>] {
+        self.0.parameters()
+    }
+
+    fn learning_rate(&self) -> T {
+        self.0.learning_rate()
+    }
+
+    fn set_learning_rate(&mut self, lr: T) {
+        self.0.set_learning_rate(lr);
+    }
+
+    fn add_param_group(&mut self, group: ParamGroup<T>) {
+        self.0.add_param_group(group);
+    }
+
+    fn step(&mut self) {
+        self.0.step();
+    }
+}
+
+/// RMSProp: divides the gradient by a running root mean square of recent gradients,
+/// s ← αs + (1 - α)g² and p ← p - lr·g/(√s + ε).
+pub struct RmsProp<T: Float = f64> {
+    params: ParamGroups<T>,
+    lr: T,
+    alpha: T,
+    eps: T,
+    weight_decay: T,
+    square_avg: Vec<T>,
+}
+
+impl<T: Float> RmsProp<T> {
+    /// Creates RMSProp with the defaults α = 0.99 and ε = 1e-8
+    pub fn new(params: Vec<Value<T>>, lr: T) -> Self {
+        let params = ParamGroups::new(params, lr);
+        let square_avg = vec![T::zero(); params.len()];
+        Self {
+            params,
+            lr,
+            alpha: T::from_f64(0.99),
+            eps: T::from_f64(1e-8),
+            weight_decay: T::zero(),
+            square_avg,
+        }
+    }
+
+    /// Decay rate of the running average of squared gradients
+    pub fn alpha(mut self, alpha: T) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn eps(mut self, eps: T) -> Self {
+        self.eps = eps;
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: T) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+
+    pub fn param_group(mut self, group: ParamGroup<T>) -> Self {
+        self.add_param_group(group);
+        self
+    }
+}
+
+impl<T: Float> Optimizer<T> for RmsProp<T> {
+    fn parameters(&self) -> &[Value<T>] {
+        &self.params.params
+    }
+
+    fn learning_rate(&self) -> T {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: T) {
+        self.lr = lr;
+    }
+
+    fn add_param_group(&mut self, group: ParamGroup<T>) {
+        self.params.add(group);
+        self.square_avg.resize(self.params.len(), T::zero());
+    }
+
+    fn step(&mut self) {
+        for (i, p, lr, weight_decay) in self.params.trainable(self.lr, self.weight_decay) {
+            let s = &mut self.square_avg[i];
+            let data = p.data();
+            let g = p.grad() + weight_decay * data;
+            *s = self.alpha * *s + (T::one() - self.alpha) * g * g;
+            p.set_data(data - lr * g / (s.sqrt() + self.eps));
+        }
+    }
+}
+
+/// Adagrad: scales each parameter's step by the inverse root of the sum of all its
+/// squared gradients so far, so frequently updated parameters slow down.
+pub struct Adagrad<T: Float = f64> {
+    params: ParamGroups<T>,
+    lr: T,
+    eps: T,
+    weight_decay: T,
+    sum_sq: Vec<T>,
+}
+
+impl<T: Float> Adagrad<T> {
+    /// Creates Adagrad with ε = 1e-10
+    pub fn new(params: Vec<Value<T>>, lr: T) -> Self {
+        let params = ParamGroups::new(params, lr);
+        let sum_sq = vec![T::zero(); params.len()];
+        Self {
+            params,
+            lr,
+            eps: T::from_f64(1e-10),
+            weight_decay: T::zero(),
+            sum_sq,
+        }
+    }
+
+    pub fn eps(mut self, eps: T) -> Self {
+        self.eps = eps;
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: T) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+
+    pub fn param_group(mut self, group: ParamGroup<T>) -> Self {
+        self.add_param_group(group);
+        self
+    }
+}
+
+impl<T: Float> Optimizer<T> for Adagrad<T> {
+    fn parameters(&self) -> &[Value<T>] {
+        &self.params.params
+    }
+
+    fn learning_rate(&self) -> T {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: T) {
+        self.lr = lr;
+    }
+
+    fn add_param_group(&mut self, group: ParamGroup<T>) {
+        self.params.add(group);
+        self.sum_sq.resize(self.params.len(), T::zero());
+    }
+
+    fn step(&mut self) {
+        for (i, p, lr, weight_decay) in self.params.trainable(self.lr, self.weight_decay) {
+            let sum = &mut self.sum_sq[i];
+            let data = p.data();
+            let g = p.grad() + weight_decay * data;
+            *sum += g * g;
+            p.set_data(data - lr * g / (sum.sqrt() + self.eps));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parameters with the given data and a gradient of 1
+    fn params(data: &[f64]) -> Vec<Value> {
+        data.iter()
+            .map(|&x| {
+                let p = Value::new(x, None, "p".to_string(), None);
+                p.set_grad(1.0);
+                p
+            })
+            .collect()
+    }
+
+    /// Runs `steps` steps of `optimizer`, keeping the gradients fixed
+    fn run(mut optimizer: impl Optimizer, steps: usize) {
+        for _ in 0..steps {
+            optimizer.step();
+        }
+    }
+
+    fn assert_close(p: &Value, expected: f64) {
+        assert!(
+            (p.data() - expected).abs() < 1e-8,
+            "{} != {}",
+            p.data(),
+            expected
+        );
+    }
+
+    #[test]
+    fn sgd_momentum_and_nesterov() {
+        let p = params(&[1.0, 1.0, 1.0]);
+        run(Sgd::new(p[..1].to_vec(), 0.1), 2);
+        assert_close(&p[0], 0.8);
+
+        // v = 1, then 0.9 + 1 = 1.9
+        run(Sgd::new(p[1..2].to_vec(), 0.1).momentum(0.9), 2);
+        assert_close(&p[1], 1.0 - 0.1 - 0.19);
+
+        // Steps along g + μv: 1 + 0.9, then 1 + 0.9 · 1.9
+        let nesterov = Sgd::new(p[2..].to_vec(), 0.1).momentum(0.9).nesterov(true);
+        run(nesterov, 2);
+        assert_close(&p[2], 1.0 - 0.19 - 0.271);
+    }
+
+    #[test]
+    fn adam_steps_by_lr() {
+        // With a constant gradient the bias-corrected averages are g and g², so every
+        // step moves each parameter by lr whatever the gradient's scale
+        let p = params(&[1.0, 1.0]);
+        p[1].set_grad(-3.0);
+        run(Adam::new(p.clone(), 0.1), 2);
+        assert_close(&p[0], 0.8);
+        assert_close(&p[1], 1.2);
+    }
+
+    #[test]
+    fn adamw_decouples_weight_decay() {
+        // Adam adds 0.5 · p to the gradient, which the adaptive scaling cancels out
+        let p = params(&[1.0]);
+        run(Adam::new(p.clone(), 0.1).weight_decay(0.5), 1);
+        assert_close(&p[0], 0.9);
+
+        // AdamW shrinks p by lr · 0.5 · p first
+        let p = params(&[1.0]);
+        run(AdamW::new(p.clone(), 0.1).weight_decay(0.5), 1);
+        assert_close(&p[0], 1.0 - 0.05 - 0.1);
+    }
+
+    #[test]
+    fn rmsprop_running_average() {
+        // s = 0.01, then 0.99 · 0.01 + 0.01
+        let p = params(&[1.0]);
+        run(RmsProp::new(p.clone(), 0.01).eps(0.0), 2);
+        assert_close(&p[0], 1.0 - 0.1 - 0.01 / 0.0199f64.sqrt());
+    }
+
+    #[test]
+    fn adagrad_accumulates_squares() {
+        // The sum of squared gradients is 1, then 2
+        let p = params(&[1.0]);
+        run(Adagrad::new(p.clone(), 0.1), 2);
+        assert_close(&p[0], 1.0 - 0.1 - 0.1 / 2f64.sqrt());
+    }
+
+    #[test]
+    fn updates_tied_parameter_once() {
+        let p = params(&[1.0, 1.0, 1.0]);
+        let tied = vec![p[0].clone(), p[1].clone(), p[0].clone()];
+        let group = ParamGroup::new(vec![p[2].clone(), p[2].clone()]).lr(0.01);
+        let mut sgd = Sgd::new(tied, 0.1).param_group(group);
+        assert_eq!(sgd.parameters().len(), 3);
+        sgd.step();
+        assert_close(&p[0], 0.9);
+        assert_close(&p[1], 0.9);
+        assert_close(&p[2], 0.99);
+    }
+
+    #[test]
+    #[should_panic(expected = "appears more than once")]
+    fn rejects_parameter_in_two_groups() {
+        let p = params(&[1.0, 2.0]);
+        Sgd::new(p.clone(), 0.1).param_group(ParamGroup::new(p[1..].to_vec()));
+    }
+
+    #[test]
+    fn group_rates_follow_the_schedule() {
+        let (body, head) = (params(&[1.0]), params(&[1.0]));
+        let mut sgd =
+            Sgd::new(head.clone(), 0.1).param_group(ParamGroup::new(body.clone()).lr(0.01));
+        sgd.step();
+        assert!((head[0].data() - 0.9).abs() < 1e-12);
+        assert!((body[0].data() - 0.99).abs() < 1e-12);
+
+        // Halving the optimizer's rate halves the group's
+        sgd.set_learning_rate(0.05);
+        sgd.step();
+        assert!((head[0].data() - 0.85).abs() < 1e-12);
+        assert!((body[0].data() - 0.985).abs() < 1e-12);
+    }
+
+    #[test]
+    fn group_added_at_zero_rate() {
+        let (body, head) = (params(&[1.0]), params(&[1.0]));
+        let mut sgd =
+            Sgd::new(head.clone(), 0.0).param_group(ParamGroup::new(body.clone()).lr(0.01));
+        sgd.step();
+        assert_eq!(head[0].data(), 1.0);
+        assert!((body[0].data() - 0.99).abs() < 1e-12);
+    }
+
+    #[test]
+    fn skips_frozen_parameters() {
+        let p = params(&[1.0, 1.0]);
+        p[0].set_requires_grad(false);
+        let mut adam = Adam::new(p.clone(), 0.1).weight_decay(0.5);
+        adam.step();
+        assert_eq!(p[0].data(), 1.0);
+        assert!(p[1].data() < 1.0);
+    }
+}
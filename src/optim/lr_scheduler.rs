@@ -0,0 +1,473 @@
+//! Learning-rate schedules.
+//!
+//! A scheduler only computes learning rates; it is advanced once per epoch (or batch)
+//! with [`LrScheduler::step`] and its rate handed to any [`Optimizer`], most easily via
+//! [`LrScheduler::update`]. Schedules that react to training progress, like
+//! [`ReduceOnPlateau`], read the monitored metric passed to `step`; the others ignore it.
+
+use std::f64::consts::PI;
+
+use super::Optimizer;
+use crate::engine::Float;
+
+/// Produces the learning rate for each step of training.
+pub trait LrScheduler<T: Float = f64> {
+    /// Learning rate for the current step
+    fn lr(&self) -> T;
+
+    /// Advances to the next step. `metric` is the monitored quantity, usually the
+    /// loss of the step that just finished.
+    fn step(&mut self, metric: T);
+
+    /// Advances the schedule and applies the new learning rate to `optimizer`,
+    /// returning it.
+    fn update(&mut self, optimizer: &mut dyn Optimizer<T>, metric: T) -> T {
+        self.step(metric);
+        let lr = self.lr();
+        optimizer.set_learning_rate(lr);
+        lr
+    }
+}
+
+/// Keeps the learning rate fixed
+pub struct Constant<T: Float = f64> {
+    lr: T,
+}
+
+impl<T: Float> Constant<T> {
+    pub fn new(lr: T) -> Self {
+        Self { lr }
+    }
+}
+
+impl<T: Float> LrScheduler<T> for Constant<T> {
+    fn lr(&self) -> T {
+        self.lr
+    }
+
+    fn step(&mut self, _metric: T) {}
+}
+
+/// Multiplies the learning rate by `gamma` every `step_size` steps
+pub struct StepDecay<T: Float = f64> {
+    base_lr: T,
+    step_size: usize,
+    gamma: T,
+    t: usize,
+}
+
+impl<T: Float> StepDecay<T> {
+    pub fn new(base_lr: T, step_size: usize, gamma: T) -> Self {
+        assert!(step_size > 0, "step_size must be positive");
+        Self {
+            base_lr,
+            step_size,
+            gamma,
+            t: 0,
+        }
+    }
+}
+
+impl<T: Float> LrScheduler<T> for StepDecay<T> {
+    fn lr(&self) -> T {
+        let decays = T::from_f64((self.t / self.step_size) as f64);
+        self.base_lr * self.gamma.powf(decays)
+    }
+
+    fn step(&mut self, _metric: T) {
+        self.t += 1;
+    }
+}
+
+/// Multiplies the learning rate by `gamma` every step: lr = base·γᵗ
+pub struct ExponentialDecay<T: Float = f64> {
+    base_lr: T,
+    gamma: T,
+    t: usize,
+}
+
+impl<T: Float> ExponentialDecay<T> {
+    pub fn new(base_lr: T, gamma: T) -> Self {
+        Self {
+            base_lr,
+            gamma,
+            t: 0,
+        }
+    }
+}
+
+impl<T: Float> LrScheduler<T> for ExponentialDecay<T> {
+    fn lr(&self) -> T {
+        self.base_lr * self.gamma.powf(T::from_f64(self.t as f64))
+    }
+
+    fn step(&mut self, _metric: T) {
+        self.t += 1;
+    }
+}
+
+/// Cosine annealing with warm restarts (SGDR): within a cycle of length Tᵢ the rate
+/// falls from `base_lr` to `min_lr` along half a cosine, then jumps back up. The first
+/// cycle lasts `period` steps and each later one `t_mult` times as long as the last.
+pub struct CosineWarmRestarts<T: Float = f64> {
+    base_lr: T,
+    min_lr: T,
+    t_mult: usize,
+    /// Steps taken in the current cycle
+    t_cur: usize,
+    /// Length of the current cycle
+    t_i: usize,
+}
+
+impl<T: Float> CosineWarmRestarts<T> {
+    pub fn new(base_lr: T, period: usize) -> Self {
+        assert!(period > 0, "period must be positive");
+        Self {
+            base_lr,
+            min_lr: T::zero(),
+            t_mult: 1,
+            t_cur: 0,
+            t_i: period,
+        }
+    }
+
+    pub fn min_lr(mut self, min_lr: T) -> Self {
+        self.min_lr = min_lr;
+        self
+    }
+
+    /// Growth factor of the cycle length after each restart
+    pub fn t_mult(mut self, t_mult: usize) -> Self {
+        assert!(t_mult > 0, "t_mult must be positive");
+        self.t_mult = t_mult;
+        self
+    }
+}
+
+impl<T: Float> LrScheduler<T> for CosineWarmRestarts<T> {
+    fn lr(&self) -> T {
+        let progress = self.t_cur as f64 / self.t_i as f64;
+        cosine_anneal(self.base_lr, self.min_lr, progress)
+    }
+
+    fn step(&mut self, _metric: T) {
+        self.t_cur += 1;
+        if self.t_cur >= self.t_i {
+            self.t_cur = 0;
+            self.t_i *= self.t_mult;
+        }
+    }
+}
+
+/// Ramps the learning rate of another schedule up linearly over the first
+/// `warmup_steps` steps, starting from `start_factor` times its rate. The wrapped
+/// schedule only starts advancing once the warmup is over.
+pub struct LinearWarmup<S, T: Float = f64> {
+    inner: S,
+    warmup_steps: usize,
+    start_factor: T,
+    t: usize,
+}
+
+impl<S: LrScheduler<T>, T: Float> LinearWarmup<S, T> {
+    /// Warms up `inner` from zero, reaching its rate after `warmup_steps` steps
+    pub fn new(inner: S, warmup_steps: usize) -> Self {
+        Self {
+            inner,
+            warmup_steps,
+            start_factor: T::zero(),
+            t: 0,
+        }
+    }
+
+    /// Fraction of the wrapped rate used on the first step
+    pub fn start_factor(mut self, start_factor: T) -> Self {
+        self.start_factor = start_factor;
+        self
+    }
+}
+
+impl<S: LrScheduler<T>, T: Float> LrScheduler<T> for LinearWarmup<S, T> {
+    fn lr(&self) -> T {
+        if self.t >= self.warmup_steps {
+            return self.inner.lr();
+        }
+        let progress = T::from_f64(self.t as f64 / self.warmup_steps as f64);
+        let factor = self.start_factor + (T::one() - self.start_factor) * progress;
+        self.inner.lr() * factor
+    }
+
+    fn step(&mut self, metric: T) {
+        if self.t < self.warmup_steps {
+            self.t += 1;
+        } else {
+            self.inner.step(metric);
+        }
+    }
+}
+
+/// The one-cycle policy: over `total_steps` steps the rate rises from
+/// `max_lr / div_factor` to `max_lr` during the first `pct_start` of training, then
+/// anneals to `max_lr / (div_factor · final_div_factor)`, both along cosine curves.
+pub struct OneCycle<T: Float = f64> {
+    max_lr: T,
+    total_steps: usize,
+    pct_start: f64,
+    div_factor: T,
+    final_div_factor: T,
+    t: usize,
+}
+
+impl<T: Float> OneCycle<T> {
+    /// Creates the policy with the usual defaults `pct_start = 0.3`,
+    /// `div_factor = 25` and `final_div_factor = 1e4`
+    pub fn new(max_lr: T, total_steps: usize) -> Self {
+        assert!(total_steps > 1, "one-cycle needs at least two steps");
+        Self {
+            max_lr,
+            total_steps,
+            pct_start: 0.3,
+            div_factor: T::from_f64(25.0),
+            final_div_factor: T::from_f64(1e4),
+            t: 0,
+        }
+    }
+
+    /// Fraction of the steps spent increasing the rate
+    pub fn pct_start(mut self, pct_start: f64) -> Self {
+        assert!(
+            (0.0..1.0).contains(&pct_start),
+            "pct_start must be in [0, 1)"
+        );
+        self.pct_start = pct_start;
+        self
+    }
+
+    pub fn div_factor(mut self, div_factor: T) -> Self {
+        self.div_factor = div_factor;
+        self
+    }
+
+    pub fn final_div_factor(mut self, final_div_factor: T) -> Self {
+        self.final_div_factor = final_div_factor;
+        self
+    }
+}
+
+impl<T: Float> LrScheduler<T> for OneCycle<T> {
+    fn lr(&self) -> T {
+        let initial_lr = self.max_lr / self.div_factor;
+        let min_lr = initial_lr / self.final_div_factor;
+        let last = (self.total_steps - 1) as f64;
+        let peak = (self.pct_start * last).round().min(last - 1.0);
+        let t = self.t as f64;
+        if t < peak {
+            cosine_anneal(initial_lr, self.max_lr, t / peak)
+        } else {
+            cosine_anneal(self.max_lr, min_lr, (t - peak) / (last - peak))
+        }
+    }
+
+    fn step(&mut self, _metric: T) {
+        // Stay at the final rate if training runs past `total_steps`
+        self.t = (self.t + 1).min(self.total_steps - 1);
+    }
+}
+
+/// Multiplies the learning rate by `factor` once the monitored metric has not
+/// improved for more than `patience` steps. Lower metrics count as better, and an
+/// improvement must beat the best value so far by a relative `threshold`.
+pub struct ReduceOnPlateau<T: Float = f64> {
+    lr: T,
+    factor: T,
+    patience: usize,
+    threshold: T,
+    min_lr: T,
+    cooldown: usize,
+    best: Option<T>,
+    bad_steps: usize,
+    cooldown_left: usize,
+}
+
+impl<T: Float> ReduceOnPlateau<T> {
+    /// Starts at `lr` with `factor = 0.1`, `patience = 10` and `threshold = 1e-4`
+    pub fn new(lr: T) -> Self {
+        Self {
+            lr,
+            factor: T::from_f64(0.1),
+            patience: 10,
+            threshold: T::from_f64(1e-4),
+            min_lr: T::zero(),
+            cooldown: 0,
+            best: None,
+            bad_steps: 0,
+            cooldown_left: 0,
+        }
+    }
+
+    pub fn factor(mut self, factor: T) -> Self {
+        assert!(
+            factor > T::zero() && factor < T::one(),
+            "factor must be in (0, 1)"
+        );
+        self.factor = factor;
+        self
+    }
+
+    /// Number of steps without improvement tolerated before reducing the rate
+    pub fn patience(mut self, patience: usize) -> Self {
+        self.patience = patience;
+        self
+    }
+
+    pub fn threshold(mut self, threshold: T) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn min_lr(mut self, min_lr: T) -> Self {
+        self.min_lr = min_lr;
+        self
+    }
+
+    /// Number of steps to wait after a reduction before counting bad steps again
+    pub fn cooldown(mut self, cooldown: usize) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+}
+
+impl<T: Float> LrScheduler<T> for ReduceOnPlateau<T> {
+    fn lr(&self) -> T {
+        self.lr
+    }
+
+    fn step(&mut self, metric: T) {
+        let improved = match self.best {
+            Some(best) => metric < best - self.threshold * best.abs(),
+            None => true,
+        };
+        if improved {
+            self.best = Some(metric);
+            self.bad_steps = 0;
+        } else {
+            self.bad_steps += 1;
+        }
+
+        if self.cooldown_left > 0 {
+            self.cooldown_left -= 1;
+            self.bad_steps = 0;
+        }
+
+        if self.bad_steps > self.patience {
+            self.lr = self.min_lr.max(self.lr * self.factor);
+            self.cooldown_left = self.cooldown;
+            self.bad_steps = 0;
+        }
+    }
+}
+
+/// Interpolates from `start` to `end` along half a cosine, `progress` going from 0 to 1
+fn cosine_anneal<T: Float>(start: T, end: T, progress: f64) -> T {
+    let weight = T::from_f64((1.0 + (PI * progress).cos()) / 2.0);
+    end + (start - end) * weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Value;
+    use crate::optim::Sgd;
+
+    /// The rate of each of the first `n` steps
+    fn rates(scheduler: &mut impl LrScheduler, n: usize) -> Vec<f64> {
+        (0..n)
+            .map(|_| {
+                let lr = scheduler.lr();
+                scheduler.step(0.0);
+                lr
+            })
+            .collect()
+    }
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-12, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn one_cycle() {
+        let mut one_cycle = OneCycle::new(1.0, 11);
+        let lrs = rates(&mut one_cycle, 12);
+        // Peaks at step round(0.3 · 10) = 3, rising along a cosine from 1/25
+        assert_close(&lrs[..4], &[0.04, 0.28, 0.76, 1.0]);
+        assert!(lrs[4..11].windows(2).all(|w| w[1] < w[0]));
+        // Ends at max_lr / (25 · 1e4), and stays there past the last step
+        assert_close(&lrs[10..], &[4e-6, 4e-6]);
+    }
+
+    #[test]
+    fn cosine_warm_restarts() {
+        let mut cosine = CosineWarmRestarts::new(1.0, 3).t_mult(2);
+        let sqrt3 = 3f64.sqrt();
+        // Restarts at step 3, then at step 3 + 6 = 9
+        let expected = [
+            1.0,
+            0.75,
+            0.25,
+            1.0,
+            (2.0 + sqrt3) / 4.0,
+            0.75,
+            0.5,
+            0.25,
+            (2.0 - sqrt3) / 4.0,
+            1.0,
+        ];
+        assert_close(&rates(&mut cosine, 10), &expected);
+    }
+
+    #[test]
+    fn linear_warmup_holds_inner_schedule() {
+        let mut warmup = LinearWarmup::new(StepDecay::new(1.0, 1, 0.5), 4);
+        let expected = [0.0, 0.25, 0.5, 0.75, 1.0, 0.5, 0.25];
+        assert_close(&rates(&mut warmup, 7), &expected);
+    }
+
+    #[test]
+    fn reduce_on_plateau() {
+        let mut plateau = ReduceOnPlateau::new(1.0)
+            .factor(0.5)
+            .patience(2)
+            .cooldown(2)
+            .min_lr(0.3);
+        let lrs: Vec<f64> = (0..12)
+            .map(|_| {
+                plateau.step(1.0);
+                plateau.lr()
+            })
+            .collect();
+        // The first step sets the best metric, and the third bad step after it is the
+        // first beyond the patience. Bad steps during the cooldown do not count, and
+        // the second reduction stops at min_lr.
+        let expected = [1.0, 1.0, 1.0, 0.5, 0.5, 0.5, 0.5, 0.5, 0.3, 0.3, 0.3, 0.3];
+        assert_close(&lrs, &expected);
+
+        // An improvement resets the count
+        let mut plateau = ReduceOnPlateau::new(1.0).patience(1);
+        for metric in [3.0, 3.0, 2.0, 2.0, 1.0] {
+            plateau.step(metric);
+        }
+        assert_eq!(plateau.lr(), 1.0);
+    }
+
+    #[test]
+    fn update_sets_optimizer_rate() {
+        let p = Value::new(1.0, None, "p".to_string(), None);
+        let mut sgd = Sgd::new(vec![p], 0.1);
+        let mut decay = StepDecay::new(0.1, 1, 0.5);
+        assert_eq!(decay.update(&mut sgd, 0.0), 0.05);
+        assert_eq!(sgd.learning_rate(), 0.05);
+    }
+}
@@ -0,0 +1,87 @@
+//! Parallel mini-batch gradient computation, available with the `sync` feature.
+//! Each sample's loss graph is built and differentiated on a rayon worker, sharing
+//! the model parameters read-only, and the per-sample gradients are then summed.
+
+use rayon::prelude::*;
+
+use crate::engine::{Float, Value};
+
+/// Evaluates `loss_fn` on every sample of `batch` in parallel and returns the loss
+/// and the gradient of each parameter in `params`, both summed over the batch.
+///
+/// Per-sample gradients are computed with [`Value::gradients`], which never writes
+/// to the shared parameters, and are reduced in sample order. The result is
+/// therefore bit-for-bit identical however rayon schedules the work. The `grad` of
+/// the parameters is left untouched; set it from the returned gradients.
+pub fn batch_gradients<T, S, F>(params: &[Value<T>], batch: &[S], loss_fn: F) -> (T, Vec<T>)
+where
+    T: Float + Send + Sync,
+    S: Sync,
+    F: Fn(&S) -> Value<T> + Sync,
+{
+    let per_sample: Vec<(T, Vec<T>)> = batch
+        .par_iter()
+        .map(|sample| {
+            let loss = loss_fn(sample);
+            (loss.data(), loss.gradients(params))
+        })
+        .collect();
+
+    let mut total_loss = T::zero();
+    let mut grads = vec![T::zero(); params.len()];
+    for (loss, sample_grads) in per_sample {
+        total_loss += loss;
+        for (g, sg) in grads.iter_mut().zip(sample_grads) {
+            *g += sg;
+        }
+    }
+    (total_loss, grads)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::nn::loss::{self, Reduction};
+    use crate::nn::{Forward, Module, MLP};
+
+    #[test]
+    fn matches_serial_sum() {
+        let model = MLP::new(2, &[4, 1], &mut StdRng::seed_from_u64(0));
+        let params = model.parameters();
+        let batch: Vec<([f64; 2], f64)> = (0..16)
+            .map(|i| {
+                let x = i as f64 / 8.0 - 1.0;
+                ([x, x * x], x.sin())
+            })
+            .collect();
+        let loss_fn = |(x, y): &([f64; 2], f64)| {
+            let x: Vec<Value> = x.iter().map(|&v| Value::from(v)).collect();
+            loss::mse(&model.forward(&x), &[Value::from(*y)], Reduction::Sum)
+        };
+
+        let mut serial_loss = 0.0;
+        let mut serial_grads = vec![0.0; params.len()];
+        for sample in &batch {
+            let loss = loss_fn(sample);
+            for p in &params {
+                p.set_grad(0.0);
+            }
+            loss.backward();
+            serial_loss += loss.data();
+            for (g, p) in serial_grads.iter_mut().zip(&params) {
+                *g += p.grad();
+            }
+        }
+
+        let (total_loss, grads) = batch_gradients(&params, &batch, loss_fn);
+        assert_eq!(total_loss, serial_loss);
+        assert_eq!(grads, serial_grads);
+
+        let bits = |grads: &[f64]| grads.iter().map(|g| g.to_bits()).collect::<Vec<_>>();
+        let (_, again) = batch_gradients(&params, &batch, loss_fn);
+        assert_eq!(bits(&again), bits(&grads));
+    }
+}
@@ -0,0 +1,258 @@
+//! A common interface over the number types a model can be evaluated with.
+//! Model code written against [`Scalar`] runs unchanged on reverse-mode [`Value`]s,
+//! forward-mode [`Dual`](crate::dual::Dual) numbers, plain numbers (for fast inference
+//! without any derivative bookkeeping), and any other implementation.
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::engine::Value;
+use crate::float::Float;
+use crate::op::Op;
+
+/// A differentiable scalar supporting the same op set as the autograd engine, built
+/// on the underlying number type `T` (`f64` unless stated otherwise).
+pub trait Scalar<T: Float = f64>:
+    Clone
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + Add<T, Output = Self>
+    + Sub<T, Output = Self>
+    + Mul<T, Output = Self>
+    + Div<T, Output = Self>
+{
+    /// Lifts a constant into this scalar type; constants carry no derivative
+    fn constant(x: T) -> Self;
+
+    /// Returns the plain numeric value
+    fn value(&self) -> T;
+
+    fn pow(&self, exponent: T) -> Self;
+    fn tanh(&self) -> Self;
+    fn relu(&self) -> Self;
+    fn leaky_relu(&self, alpha: T) -> Self;
+    fn elu(&self) -> Self;
+    fn exp(&self) -> Self;
+    fn ln(&self) -> Self;
+    fn log2(&self) -> Self;
+    fn sigmoid(&self) -> Self;
+    fn softplus(&self) -> Self;
+    fn gelu(&self) -> Self;
+    fn silu(&self) -> Self;
+    fn abs(&self) -> Self;
+    fn sqrt(&self) -> Self;
+    fn sin(&self) -> Self;
+    fn cos(&self) -> Self;
+
+    /// Sums a slice. Graph-based implementations record this as one fused node.
+    fn sum(xs: &[Self]) -> Self {
+        xs.iter()
+            .cloned()
+            .reduce(|acc, x| acc + x)
+            .unwrap_or_else(|| Self::constant(T::zero()))
+    }
+
+    /// Computes the dot product Σ aᵢbᵢ of two slices of equal length.
+    /// Graph-based implementations record this as one fused node.
+    fn dot(a: &[Self], b: &[Self]) -> Self {
+        assert_eq!(
+            a.len(),
+            b.len(),
+            "dot product of vectors of different lengths"
+        );
+        a.iter()
+            .zip(b)
+            .map(|(x, y)| x.clone() * y.clone())
+            .reduce(|acc, x| acc + x)
+            .unwrap_or_else(|| Self::constant(T::zero()))
+    }
+
+    /// Computes ln Σ e^xᵢ, subtracting the maximum first so that nothing overflows.
+    /// Graph-based implementations record this as one fused node.
+    fn logsumexp(xs: &[Self]) -> Self {
+        let m = xs
+            .iter()
+            .map(Self::value)
+            .reduce(T::max)
+            .unwrap_or(T::from_f64(f64::NEG_INFINITY));
+        if m.to_f64().is_infinite() {
+            return Self::constant(m);
+        }
+        let shifted: Vec<Self> = xs.iter().map(|x| (x.clone() - m).exp()).collect();
+        Self::sum(&shifted).ln() + m
+    }
+
+    /// Computes xᵢ - logsumexp(x) for every element
+    fn log_softmax(xs: &[Self]) -> Vec<Self> {
+        let lse = Self::logsumexp(xs);
+        xs.iter().map(|x| x.clone() - lse.clone()).collect()
+    }
+
+    /// Computes e^xᵢ / Σ e^xⱼ for every element, via the stable [`Scalar::log_softmax`]
+    fn softmax(xs: &[Self]) -> Vec<Self> {
+        Self::log_softmax(xs).iter().map(Self::exp).collect()
+    }
+}
+
+impl<T: Float> Scalar<T> for Value<T> {
+    fn constant(x: T) -> Self {
+        Value::from(x)
+    }
+
+    fn value(&self) -> T {
+        self.data()
+    }
+
+    fn pow(&self, exponent: T) -> Self {
+        Value::pow(self, exponent)
+    }
+
+    fn tanh(&self) -> Self {
+        Value::tanh(self)
+    }
+
+    fn relu(&self) -> Self {
+        Value::relu(self)
+    }
+
+    fn leaky_relu(&self, alpha: T) -> Self {
+        Value::leaky_relu(self, alpha)
+    }
+
+    fn elu(&self) -> Self {
+        Value::elu(self)
+    }
+
+    fn exp(&self) -> Self {
+        Value::exp(self)
+    }
+
+    fn ln(&self) -> Self {
+        Value::ln(self)
+    }
+
+    fn log2(&self) -> Self {
+        Value::log2(self)
+    }
+
+    fn sigmoid(&self) -> Self {
+        Value::sigmoid(self)
+    }
+
+    fn softplus(&self) -> Self {
+        Value::softplus(self)
+    }
+
+    fn gelu(&self) -> Self {
+        Value::gelu(self)
+    }
+
+    fn silu(&self) -> Self {
+        Value::silu(self)
+    }
+
+    fn abs(&self) -> Self {
+        Value::abs(self)
+    }
+
+    fn sqrt(&self) -> Self {
+        Value::sqrt(self)
+    }
+
+    fn sin(&self) -> Self {
+        Value::sin(self)
+    }
+
+    fn cos(&self) -> Self {
+        Value::cos(self)
+    }
+
+    fn sum(xs: &[Self]) -> Self {
+        Value::sum(xs)
+    }
+
+    fn dot(a: &[Self], b: &[Self]) -> Self {
+        Value::dot(a, b)
+    }
+
+    fn logsumexp(xs: &[Self]) -> Self {
+        Value::logsumexp(xs)
+    }
+}
+
+/// Plain numbers, evaluated with the same forward formulas as the engine ops.
+impl<T: Float> Scalar<T> for T {
+    fn constant(x: T) -> Self {
+        x
+    }
+
+    fn value(&self) -> T {
+        *self
+    }
+
+    fn pow(&self, exponent: T) -> Self {
+        Op::Pow(exponent).forward(&[*self])
+    }
+
+    fn tanh(&self) -> Self {
+        Float::tanh(*self)
+    }
+
+    fn relu(&self) -> Self {
+        Op::Relu.forward(&[*self])
+    }
+
+    fn leaky_relu(&self, alpha: T) -> Self {
+        Op::LeakyRelu(alpha).forward(&[*self])
+    }
+
+    fn elu(&self) -> Self {
+        Op::Elu.forward(&[*self])
+    }
+
+    fn exp(&self) -> Self {
+        Float::exp(*self)
+    }
+
+    fn ln(&self) -> Self {
+        Float::ln(*self)
+    }
+
+    fn log2(&self) -> Self {
+        Float::log2(*self)
+    }
+
+    fn sigmoid(&self) -> Self {
+        Op::Sigmoid.forward(&[*self])
+    }
+
+    fn softplus(&self) -> Self {
+        Op::Softplus.forward(&[*self])
+    }
+
+    fn gelu(&self) -> Self {
+        Op::Gelu.forward(&[*self])
+    }
+
+    fn silu(&self) -> Self {
+        Op::Silu.forward(&[*self])
+    }
+
+    fn abs(&self) -> Self {
+        Float::abs(*self)
+    }
+
+    fn sqrt(&self) -> Self {
+        Float::sqrt(*self)
+    }
+
+    fn sin(&self) -> Self {
+        Float::sin(*self)
+    }
+
+    fn cos(&self) -> Self {
+        Float::cos(*self)
+    }
+}
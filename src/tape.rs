@@ -0,0 +1,420 @@
+//! An arena (tape) backend for the autograd engine.
+//!
+//! Where every [`Value`] is its own `Rc<RefCell<..>>` allocation, a [`Graph`] records
+//! nodes as entries in a single `Vec` and hands out lightweight [`Var`] indices into it.
+//! Nodes are appended in creation order, which is already a topological order, so
+//! backprop is a single linear sweep over the tape in reverse. `Var` implements
+//! [`Scalar`], so model code such as `MLP::forward_with` runs on either backend.
+
+use std::{
+    cell::RefCell,
+    fmt::{Debug, Display},
+    ops::{Add, Div, Mul, Neg, Range, Sub},
+};
+
+use crate::engine::Value;
+use crate::float::Float;
+use crate::op::{CustomOpRef, Op};
+use crate::scalar::Scalar;
+
+/// A tape of recorded operations. Create one per forward/backward pass, or call
+/// [`Graph::clear`] to reuse its allocations once all of its `Var`s are gone.
+pub struct Graph<T: Float = f64> {
+    inner: RefCell<Tape<T>>,
+}
+
+struct Tape<T: Float> {
+    nodes: Vec<Node<T>>,
+    /// Input indices of all nodes, each node owning a contiguous range
+    edges: Vec<usize>,
+    /// Gradients from the last backward pass, indexed like `nodes`
+    grads: Vec<T>,
+    /// Parameters lifted with `Graph::param`, with their index on the tape
+    params: Vec<(usize, Value<T>)>,
+}
+
+struct Node<T: Float> {
+    data: T,
+    op: Option<Op<T>>,
+    inputs: Range<usize>,
+}
+
+/// A scalar recorded on a [`Graph`]. Constants created through
+/// [`Scalar::constant`] have no graph and are only recorded once they are
+/// combined with a variable.
+#[derive(Clone, Copy)]
+pub struct Var<'g, T: Float = f64> {
+    graph: Option<&'g Graph<T>>,
+    index: usize,
+    data: T,
+}
+
+impl<T: Float> Graph<T> {
+    pub fn new() -> Self {
+        Self {
+            inner: RefCell::new(Tape {
+                nodes: Vec::new(),
+                edges: Vec::new(),
+                grads: Vec::new(),
+                params: Vec::new(),
+            }),
+        }
+    }
+
+    /// Records a new leaf variable
+    pub fn var(&self, data: T) -> Var<'_, T> {
+        let index = self.inner.borrow_mut().push(data, None, &[]);
+        Var {
+            graph: Some(self),
+            index,
+            data,
+        }
+    }
+
+    /// Records a leaf holding the current data of a parameter `Value`, so that its
+    /// tape gradient can later be written back with [`Graph::accumulate_param_grads`].
+    /// Pass this as the lifting closure of `MLP::forward_with` to train on the tape.
+    pub fn param(&self, p: &Value<T>) -> Var<'_, T> {
+        let var = self.var(p.data());
+        self.inner.borrow_mut().params.push((var.index, p.clone()));
+        var
+    }
+
+    /// Adds the gradient of every lifted parameter from the last backward pass to
+    /// the `grad` of the `Value` it was lifted from. Parameters that do not require a
+    /// gradient (see [`Value::set_requires_grad`]) are left untouched.
+    pub fn accumulate_param_grads(&self) {
+        let tape = self.inner.borrow();
+        for (index, p) in tape.params.iter().filter(|(_, p)| p.requires_grad()) {
+            p.set_grad(p.grad() + tape.grads.get(*index).copied().unwrap_or(T::zero()));
+        }
+    }
+
+    /// Empties the tape while keeping its allocations for the next pass
+    pub fn clear(&mut self) {
+        let tape = self.inner.get_mut();
+        tape.nodes.clear();
+        tape.edges.clear();
+        tape.grads.clear();
+        tape.params.clear();
+    }
+
+    /// Records `op` applied to `inputs`, pushing any constants onto the tape first.
+    fn apply<'g>(&'g self, op: Op<T>, inputs: &[Var<'g, T>]) -> Var<'g, T> {
+        let data: Vec<T> = inputs.iter().map(|v| v.data).collect();
+        let out = op.forward(&data);
+
+        let mut tape = self.inner.borrow_mut();
+        let indices: Vec<usize> = inputs
+            .iter()
+            .map(|v| match v.graph {
+                Some(graph) => {
+                    assert!(
+                        std::ptr::eq(graph, self),
+                        "cannot combine variables from different graphs"
+                    );
+                    v.index
+                }
+                None => tape.push(v.data, None, &[]),
+            })
+            .collect();
+        let index = tape.push(out, Some(op), &indices);
+
+        Var {
+            graph: Some(self),
+            index,
+            data: out,
+        }
+    }
+}
+
+impl<T: Float> Default for Graph<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs `f` on a fresh tape, backpropagates from the scalar it returns and adds the
+/// gradients of the parameters it lifted with [`Graph::param`] to their `grad`.
+/// Returns the value of the output.
+pub fn backward<T, F>(f: F) -> T
+where
+    T: Float,
+    F: for<'g> FnOnce(&'g Graph<T>) -> Var<'g, T>,
+{
+    let graph = Graph::new();
+    let out = f(&graph);
+    out.backward();
+    graph.accumulate_param_grads();
+    out.data()
+}
+
+impl<T: Float> Tape<T> {
+    fn push(&mut self, data: T, op: Option<Op<T>>, inputs: &[usize]) -> usize {
+        let start = self.edges.len();
+        self.edges.extend_from_slice(inputs);
+        self.nodes.push(Node {
+            data,
+            op,
+            inputs: start..self.edges.len(),
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Reverse sweep from `output`: every node after it on the tape cannot
+    /// contribute, and every node before it has all of its consumers later on.
+    fn backward(&mut self, output: usize) {
+        let Tape {
+            nodes,
+            edges,
+            grads,
+            ..
+        } = self;
+        grads.clear();
+        grads.resize(nodes.len(), T::zero());
+        grads[output] = T::one();
+
+        let mut data = Vec::new();
+        for i in (0..=output).rev() {
+            let grad = grads[i];
+            let node = &nodes[i];
+            let Some(ref op) = node.op else {
+                continue;
+            };
+            let inputs = &edges[node.inputs.clone()];
+            data.clear();
+            data.extend(inputs.iter().map(|&j| nodes[j].data));
+            for (&j, local_grad) in inputs.iter().zip(op.local_grads(&data, node.data)) {
+                grads[j] += local_grad * grad;
+            }
+        }
+    }
+}
+
+impl<'g, T: Float> Var<'g, T> {
+    /// Returns the scalar value stored in this node
+    pub fn data(&self) -> T {
+        self.data
+    }
+
+    /// Returns the gradient from the last backward pass on this variable's graph
+    pub fn grad(&self) -> T {
+        self.graph
+            .and_then(|graph| graph.inner.borrow().grads.get(self.index).copied())
+            .unwrap_or(T::zero())
+    }
+
+    /// Computes ∂self/∂x for every variable x recorded before this one on the tape.
+    /// Gradients from any previous backward pass on the same graph are replaced.
+    pub fn backward(&self) {
+        if let Some(graph) = self.graph {
+            graph.inner.borrow_mut().backward(self.index);
+        }
+    }
+
+    /// Applies a user-defined differentiable function to the given inputs.
+    pub fn custom(op: CustomOpRef<T>, inputs: &[Var<'g, T>]) -> Var<'g, T> {
+        Var::apply(Op::Custom(op), inputs)
+    }
+
+    fn apply(op: Op<T>, inputs: &[Var<'g, T>]) -> Var<'g, T> {
+        match inputs.iter().find_map(|v| v.graph) {
+            Some(graph) => graph.apply(op, inputs),
+            // Ops on constants only produce another constant
+            None => {
+                let data: Vec<T> = inputs.iter().map(|v| v.data).collect();
+                Var::constant(op.forward(&data))
+            }
+        }
+    }
+}
+
+/// Implements a binary operator for `Var op Var`, `Var op T` and, for the listed
+/// primitive types, `T op Var`.
+macro_rules! impl_binary_op {
+    ($trait:ident, $method:ident, $op:expr; $($float:ty),*) => {
+        impl<'g, T: Float> $trait for Var<'g, T> {
+            type Output = Var<'g, T>;
+            fn $method(self, rhs: Var<'g, T>) -> Var<'g, T> {
+                Var::apply($op, &[self, rhs])
+            }
+        }
+
+        impl<'g, T: Float> $trait<T> for Var<'g, T> {
+            type Output = Var<'g, T>;
+            fn $method(self, rhs: T) -> Var<'g, T> {
+                Var::apply($op, &[self, Var::constant(rhs)])
+            }
+        }
+
+        $(
+            impl<'g> $trait<Var<'g, $float>> for $float {
+                type Output = Var<'g, $float>;
+                fn $method(self, rhs: Var<'g, $float>) -> Var<'g, $float> {
+                    Var::apply($op, &[Var::constant(self), rhs])
+                }
+            }
+        )*
+    };
+}
+
+impl_binary_op!(Add, add, Op::Add; f32, f64);
+impl_binary_op!(Sub, sub, Op::Sub; f32, f64);
+impl_binary_op!(Mul, mul, Op::Mul; f32, f64);
+impl_binary_op!(Div, div, Op::Div; f32, f64);
+
+impl<'g, T: Float> Neg for Var<'g, T> {
+    type Output = Var<'g, T>;
+    fn neg(self) -> Var<'g, T> {
+        Var::apply(Op::Neg, &[self])
+    }
+}
+
+impl<T: Float> Scalar<T> for Var<'_, T> {
+    fn constant(x: T) -> Self {
+        Var {
+            graph: None,
+            index: 0,
+            data: x,
+        }
+    }
+
+    fn value(&self) -> T {
+        self.data
+    }
+
+    fn pow(&self, exponent: T) -> Self {
+        Var::apply(Op::Pow(exponent), &[*self])
+    }
+
+    fn tanh(&self) -> Self {
+        Var::apply(Op::Tanh, &[*self])
+    }
+
+    fn relu(&self) -> Self {
+        Var::apply(Op::Relu, &[*self])
+    }
+
+    fn leaky_relu(&self, alpha: T) -> Self {
+        Var::apply(Op::LeakyRelu(alpha), &[*self])
+    }
+
+    fn elu(&self) -> Self {
+        Var::apply(Op::Elu, &[*self])
+    }
+
+    fn exp(&self) -> Self {
+        Var::apply(Op::Exp, &[*self])
+    }
+
+    fn ln(&self) -> Self {
+        Var::apply(Op::Ln, &[*self])
+    }
+
+    fn log2(&self) -> Self {
+        Var::apply(Op::Log2, &[*self])
+    }
+
+    fn sigmoid(&self) -> Self {
+        Var::apply(Op::Sigmoid, &[*self])
+    }
+
+    fn softplus(&self) -> Self {
+        Var::apply(Op::Softplus, &[*self])
+    }
+
+    fn gelu(&self) -> Self {
+        Var::apply(Op::Gelu, &[*self])
+    }
+
+    fn silu(&self) -> Self {
+        Var::apply(Op::Silu, &[*self])
+    }
+
+    fn abs(&self) -> Self {
+        Var::apply(Op::Abs, &[*self])
+    }
+
+    fn sqrt(&self) -> Self {
+        Var::apply(Op::Sqrt, &[*self])
+    }
+
+    fn sin(&self) -> Self {
+        Var::apply(Op::Sin, &[*self])
+    }
+
+    fn cos(&self) -> Self {
+        Var::apply(Op::Cos, &[*self])
+    }
+
+    fn sum(xs: &[Self]) -> Self {
+        if xs.is_empty() {
+            return Var::constant(T::zero());
+        }
+        Var::apply(Op::Sum, xs)
+    }
+
+    fn dot(a: &[Self], b: &[Self]) -> Self {
+        assert_eq!(
+            a.len(),
+            b.len(),
+            "dot product of vectors of different lengths"
+        );
+        if a.is_empty() {
+            return Var::constant(T::zero());
+        }
+        let inputs: Vec<Self> = a.iter().chain(b).copied().collect();
+        Var::apply(Op::Dot, &inputs)
+    }
+
+    fn logsumexp(xs: &[Self]) -> Self {
+        if xs.is_empty() {
+            return Var::constant(T::from_f64(f64::NEG_INFINITY));
+        }
+        Var::apply(Op::LogSumExp, xs)
+    }
+}
+
+impl<T: Float> Debug for Var<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Var(data: {}, grad: {})", self.data(), self.grad())
+    }
+}
+
+impl<T: Float> Display for Var<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Var(data: {}, grad: {})", self.data(), self.grad())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::nn::{Forward, Module, MLP};
+
+    #[test]
+    fn mlp_gradients_match_values() {
+        let mut model = MLP::new(3, &[4, 4, 2], &mut StdRng::seed_from_u64(0));
+        let x = [0.5, -1.2, 0.8];
+        let params = model.parameters();
+
+        let xs: Vec<Value> = x.iter().map(|&xi| Value::from(xi)).collect();
+        let out = model.forward(&xs).into_iter().sum::<Value>();
+        out.backward();
+        let value_grads: Vec<f64> = params.iter().map(Value::grad).collect();
+
+        model.zero_grad();
+        let data = backward(|graph| {
+            let xs = x.iter().map(|&xi| graph.var(xi)).collect();
+            let outs = model.forward_with(xs, &mut |p| graph.param(p));
+            outs.into_iter().reduce(|acc, o| acc + o).unwrap()
+        });
+        assert!((data - out.data()).abs() < 1e-12);
+        for (p, g) in params.iter().zip(value_grads) {
+            assert!((p.grad() - g).abs() < 1e-12, "{} != {}", p.grad(), g);
+        }
+    }
+}
@@ -0,0 +1,397 @@
+//! An n-dimensional array of scalars with numpy-style broadcasting.
+//! A [`Tensor`] holds any [`Scalar`] (usually [`Value`]s) in row-major order. Reductions
+//! and `matmul` produce each output element with a single fused `sum`/`dot` node, so
+//! their backward is one vector operation per output rather than a chain of scalar nodes.
+
+use std::ops::{Add, Div, Mul, Sub};
+
+use crate::engine::{Float, Value};
+use crate::scalar::Scalar;
+
+/// A row-major n-dimensional array. A tensor with an empty shape holds one scalar.
+#[derive(Debug, Clone)]
+pub struct Tensor<S = Value> {
+    data: Vec<S>,
+    shape: Vec<usize>,
+}
+
+impl<S> Tensor<S> {
+    /// Creates a tensor from row-major data, which must hold exactly as many
+    /// elements as the shape describes.
+    pub fn new(data: Vec<S>, shape: &[usize]) -> Self {
+        assert_eq!(
+            data.len(),
+            shape.iter().product::<usize>(),
+            "{} elements do not fit shape {:?}",
+            data.len(),
+            shape
+        );
+        Self {
+            data,
+            shape: shape.to_vec(),
+        }
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    /// Number of axes
+    pub fn ndim(&self) -> usize {
+        self.shape.len()
+    }
+
+    /// Total number of elements
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the elements in row-major order
+    pub fn as_slice(&self) -> &[S] {
+        &self.data
+    }
+
+    pub fn into_vec(self) -> Vec<S> {
+        self.data
+    }
+
+    /// Returns the element at a multi-dimensional index
+    pub fn get(&self, index: &[usize]) -> &S {
+        assert_eq!(
+            index.len(),
+            self.ndim(),
+            "index {:?} does not match shape {:?}",
+            index,
+            self.shape
+        );
+        let offset = index
+            .iter()
+            .zip(&self.shape)
+            .zip(strides(&self.shape))
+            .map(|((&i, &dim), stride)| {
+                assert!(
+                    i < dim,
+                    "index {:?} is out of bounds for shape {:?}",
+                    index,
+                    self.shape
+                );
+                i * stride
+            })
+            .sum::<usize>();
+        &self.data[offset]
+    }
+
+    /// Applies `f` to every element
+    pub fn map<R, F: FnMut(&S) -> R>(&self, f: F) -> Tensor<R> {
+        Tensor {
+            data: self.data.iter().map(f).collect(),
+            shape: self.shape.clone(),
+        }
+    }
+}
+
+impl<S: Clone> Tensor<S> {
+    /// Returns a tensor with the same elements in a new shape of the same size
+    pub fn reshape(&self, shape: &[usize]) -> Self {
+        Tensor::new(self.data.clone(), shape)
+    }
+
+    /// Reverses the order of the axes; for a matrix this is the usual transpose.
+    pub fn transpose(&self) -> Self {
+        let shape: Vec<usize> = self.shape.iter().rev().copied().collect();
+        let src_strides: Vec<usize> = strides(&self.shape).into_iter().rev().collect();
+        let data = (0..self.len())
+            .map(|i| self.data[offset(i, &shape, &src_strides)].clone())
+            .collect();
+        Tensor { data, shape }
+    }
+
+    /// Combines two tensors elementwise, broadcasting them to a common shape first.
+    ///
+    /// Shapes are aligned from the last axis, and each pair of axes must either be
+    /// equal or one of them must be 1, in which case it is repeated along the other.
+    pub fn zip_with<F: FnMut(&S, &S) -> S>(&self, other: &Tensor<S>, mut f: F) -> Self {
+        let shape = broadcast_shape(&self.shape, &other.shape);
+        let lhs = broadcast_strides(&self.shape, &shape);
+        let rhs = broadcast_strides(&other.shape, &shape);
+        let data = (0..shape.iter().product())
+            .map(|i| {
+                f(
+                    &self.data[offset(i, &shape, &lhs)],
+                    &other.data[offset(i, &shape, &rhs)],
+                )
+            })
+            .collect();
+        Tensor { data, shape }
+    }
+
+    /// Splits the tensor around `axis` into the lanes that run along it, returning them
+    /// together with the shape that remains once the axis is removed.
+    fn lanes(&self, axis: usize) -> (Vec<Vec<S>>, Vec<usize>) {
+        assert!(
+            axis < self.ndim(),
+            "axis {} is out of range for shape {:?}",
+            axis,
+            self.shape
+        );
+        let dim = self.shape[axis];
+        let inner: usize = self.shape[axis + 1..].iter().product();
+        let outer: usize = self.shape[..axis].iter().product();
+        let lanes = (0..outer)
+            .flat_map(|o| (0..inner).map(move |i| (o, i)))
+            .map(|(o, i)| {
+                (0..dim)
+                    .map(|j| self.data[(o * dim + j) * inner + i].clone())
+                    .collect()
+            })
+            .collect();
+        let mut shape = self.shape.clone();
+        shape.remove(axis);
+        (lanes, shape)
+    }
+}
+
+impl<S> Tensor<S> {
+    /// Sums all elements into one fused node
+    pub fn sum<T: Float>(&self) -> S
+    where
+        S: Scalar<T>,
+    {
+        S::sum(&self.data)
+    }
+
+    /// Averages all elements
+    pub fn mean<T: Float>(&self) -> S
+    where
+        S: Scalar<T>,
+    {
+        self.sum() / T::from_f64(self.len() as f64)
+    }
+
+    /// Sums along `axis`, removing it from the shape
+    pub fn sum_axis<T: Float>(&self, axis: usize) -> Tensor<S>
+    where
+        S: Scalar<T>,
+    {
+        let (lanes, shape) = self.lanes(axis);
+        Tensor {
+            data: lanes.iter().map(|lane| S::sum(lane)).collect(),
+            shape,
+        }
+    }
+
+    /// Averages along `axis`, removing it from the shape
+    pub fn mean_axis<T: Float>(&self, axis: usize) -> Tensor<S>
+    where
+        S: Scalar<T>,
+    {
+        let sum = self.sum_axis(axis);
+        let n = T::from_f64(self.shape[axis] as f64);
+        sum.map(|s| s.clone() / n)
+    }
+
+    /// Matrix product. A 1-D operand is treated as a column vector on the right and as
+    /// a row vector on the left, and that axis is dropped from the result, so this also
+    /// covers matrix-vector products and dot products.
+    pub fn matmul<T: Float>(&self, other: &Tensor<S>) -> Tensor<S>
+    where
+        S: Scalar<T>,
+    {
+        assert!(
+            (1..=2).contains(&self.ndim()) && (1..=2).contains(&other.ndim()),
+            "matmul expects 1-D or 2-D tensors, got shapes {:?} and {:?}",
+            self.shape,
+            other.shape
+        );
+        let (m, k) = match self.shape[..] {
+            [k] => (1, k),
+            [m, k] => (m, k),
+            _ => unreachable!(),
+        };
+        let (k2, n) = match other.shape[..] {
+            [k] => (k, 1),
+            [k, n] => (k, n),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            k, k2,
+            "matmul: shapes {:?} and {:?} are not aligned",
+            self.shape, other.shape
+        );
+
+        // An empty inner dimension sums nothing, and would leave `chunks` below nothing
+        // to split on
+        if k == 0 {
+            let data = (0..m * n).map(|_| S::constant(T::zero())).collect();
+            return Tensor {
+                data,
+                shape: matmul_shape(self, other, m, n),
+            };
+        }
+
+        // Gather the columns of `other` once, so every output is a dot of two lanes
+        let columns = if other.ndim() == 1 {
+            vec![other.data.clone()]
+        } else {
+            other
+                .transpose()
+                .data
+                .chunks(k)
+                .map(<[S]>::to_vec)
+                .collect()
+        };
+        let data = self
+            .data
+            .chunks(k)
+            .flat_map(|row| columns.iter().map(move |col| S::dot(row, col)))
+            .collect();
+
+        Tensor {
+            data,
+            shape: matmul_shape(self, other, m, n),
+        }
+    }
+}
+
+/// Shape of an `m × n` matrix product, without the axes of 1-D operands
+fn matmul_shape<S>(a: &Tensor<S>, b: &Tensor<S>, m: usize, n: usize) -> Vec<usize> {
+    let mut shape = Vec::new();
+    if a.ndim() == 2 {
+        shape.push(m);
+    }
+    if b.ndim() == 2 {
+        shape.push(n);
+    }
+    shape
+}
+
+impl<T: Float> Tensor<Value<T>> {
+    /// Returns the data of every element
+    pub fn data(&self) -> Tensor<T> {
+        self.map(Value::data)
+    }
+
+    /// Returns the gradient of every element
+    pub fn grad(&self) -> Tensor<T> {
+        self.map(Value::grad)
+    }
+}
+
+/// Wraps a vector as a 1-D tensor
+impl<S> From<Vec<S>> for Tensor<S> {
+    fn from(data: Vec<S>) -> Self {
+        let shape = vec![data.len()];
+        Self { data, shape }
+    }
+}
+
+/// Implements an elementwise, broadcasting binary operator for every combination of
+/// `Tensor` and `&Tensor` operands.
+macro_rules! impl_binary_op {
+    ($trait:ident, $method:ident) => {
+        impl<S: Clone + $trait<Output = S>> $trait<&Tensor<S>> for &Tensor<S> {
+            type Output = Tensor<S>;
+            fn $method(self, rhs: &Tensor<S>) -> Tensor<S> {
+                self.zip_with(rhs, |a, b| a.clone().$method(b.clone()))
+            }
+        }
+
+        impl<S: Clone + $trait<Output = S>> $trait<Tensor<S>> for &Tensor<S> {
+            type Output = Tensor<S>;
+            fn $method(self, rhs: Tensor<S>) -> Tensor<S> {
+                self.$method(&rhs)
+            }
+        }
+
+        impl<S: Clone + $trait<Output = S>> $trait<&Tensor<S>> for Tensor<S> {
+            type Output = Tensor<S>;
+            fn $method(self, rhs: &Tensor<S>) -> Tensor<S> {
+                (&self).$method(rhs)
+            }
+        }
+
+        impl<S: Clone + $trait<Output = S>> $trait<Tensor<S>> for Tensor<S> {
+            type Output = Tensor<S>;
+            fn $method(self, rhs: Tensor<S>) -> Tensor<S> {
+                (&self).$method(&rhs)
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add);
+impl_binary_op!(Sub, sub);
+impl_binary_op!(Mul, mul);
+impl_binary_op!(Div, div);
+
+/// Row-major strides of a shape, in elements
+fn strides(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+    for i in (0..shape.len().saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1] * shape[i + 1];
+    }
+    strides
+}
+
+/// Maps the `i`-th element of a row-major array of `shape` to an offset in a source
+/// array with the given strides (one per axis of `shape`).
+fn offset(mut i: usize, shape: &[usize], strides: &[usize]) -> usize {
+    let mut offset = 0;
+    for (&dim, &stride) in shape.iter().zip(strides).rev() {
+        offset += (i % dim) * stride;
+        i /= dim;
+    }
+    offset
+}
+
+/// The shape two tensors broadcast to, panicking if they are incompatible
+fn broadcast_shape(a: &[usize], b: &[usize]) -> Vec<usize> {
+    let ndim = a.len().max(b.len());
+    let dim = |shape: &[usize], axis: usize| {
+        (axis + shape.len())
+            .checked_sub(ndim)
+            .map_or(1, |i| shape[i])
+    };
+    (0..ndim)
+        .map(|axis| match (dim(a, axis), dim(b, axis)) {
+            (x, y) if x == y => x,
+            (1, y) => y,
+            (x, 1) => x,
+            _ => panic!("cannot broadcast shapes {:?} and {:?}", a, b),
+        })
+        .collect()
+}
+
+/// Strides for reading a tensor of `shape` as if it had the broadcast shape `to`:
+/// missing and size-1 axes get a stride of 0, so the same element is repeated.
+fn broadcast_strides(shape: &[usize], to: &[usize]) -> Vec<usize> {
+    let own = strides(shape);
+    let pad = to.len() - shape.len();
+    (0..to.len())
+        .map(|axis| match axis.checked_sub(pad) {
+            Some(i) if shape[i] != 1 => own[i],
+            _ => 0,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matmul_with_empty_inner_dimension() {
+        let a: Tensor<Value> = Tensor::new(Vec::new(), &[2, 0]);
+        let b: Tensor<Value> = Tensor::new(Vec::new(), &[0, 3]);
+        let c = a.matmul(&b);
+        assert_eq!(c.shape(), &[2, 3]);
+        assert!(c.data().as_slice().iter().all(|&x| x == 0.0));
+
+        let v: Tensor<Value> = Tensor::new(Vec::new(), &[0]);
+        assert_eq!(a.matmul(&v).shape(), &[2]);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::Value;
+use crate::engine::{Float, Value};
 use colored::*;
 use std::collections::HashSet;
 
@@ -20,7 +20,7 @@ impl BackpropViz {
         }
     }
 
-    pub fn draw_step(&self, value: &Value, step_desc: &str) {
+    pub fn draw_step<T: Float>(&self, value: &Value<T>, step_desc: &str) {
         println!("\n{}", "Current Operation:".bright_blue().bold());
         println!("{}", step_desc);
         println!("\n{}", "Computation Graph:".bright_green().bold());
@@ -30,59 +30,56 @@ impl BackpropViz {
         std::io::stdin().read_line(&mut input).unwrap();
     }
 
-    fn draw_graph(&self, value: &Value) {
+    fn draw_graph<T: Float>(&self, value: &Value<T>) {
         let mut result = String::new();
+        let labels = value.labels();
         let mut visited = HashSet::new();
-        self.draw_node_recursive(value, &mut result, &mut visited, "", true);
-        println!("{}", result);
-    }
+        // Explicit stack of (node, prefix, is_last) so deep graphs cannot overflow
+        let mut stack = vec![(value.clone(), String::new(), true)];
 
-    fn draw_node_recursive(
-        &self,
-        value: &Value,
-        result: &mut String,
-        visited: &mut HashSet<usize>,
-        prefix: &str,
-        is_last: bool,
-    ) {
-        let ptr = value as *const Value as usize;
-        if visited.contains(&ptr) {
-            return;
-        }
-        visited.insert(ptr);
-
-        let node_str = format!(
-            "{}{} {} [data={:.4}, grad={:.4}]",
-            prefix,
-            if is_last { "└─" } else { "├─" },
-            value.label(),
-            value.data(),
-            value.grad()
-        );
-
-        let colored_str = if self.active_nodes.contains(&ptr) {
-            node_str.bright_yellow().bold().to_string()
-        } else if self.completed_nodes.contains(&ptr) {
-            node_str.bright_green().to_string()
-        } else {
-            node_str.normal().to_string()
-        };
-
-        result.push_str(&format!("{}\n", colored_str));
-
-        if !value.prev().is_empty() {
-            let new_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
-
-            for (i, child) in value.prev().iter().enumerate() {
-                self.draw_node_recursive(
-                    child,
-                    result,
-                    visited,
-                    &new_prefix,
-                    i == value.prev().len() - 1,
-                );
+        while let Some((node, prefix, is_last)) = stack.pop() {
+            let ptr = node.id();
+            if !visited.insert(ptr) {
+                continue;
+            }
+
+            let node_str = format!(
+                "{}{} {} [data={:.4}, grad={:.4}]",
+                prefix,
+                if is_last { "└─" } else { "├─" },
+                labels[&ptr],
+                node.data(),
+                node.grad()
+            );
+
+            let colored_str = if self.active_nodes.contains(&ptr) {
+                node_str.bright_yellow().bold().to_string()
+            } else if self.completed_nodes.contains(&ptr) {
+                node_str.bright_green().to_string()
+            } else {
+                node_str.normal().to_string()
+            };
+
+            result.push_str(&format!("{}\n", colored_str));
+
+            let prev = node.prev();
+            if !prev.is_empty() {
+                let new_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+
+                // Push in reverse so children are drawn in their original order
+                for (i, child) in prev.iter().enumerate().rev() {
+                    stack.push((child.clone(), new_prefix.clone(), i == prev.len() - 1));
+                }
             }
         }
+
+        println!("{}", result);
+    }
+}
+
+impl Default for BackpropViz {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -97,7 +94,7 @@ pub fn plot_losses(losses: &[f64], filename: &str) -> Result<()> {
         .y_label_area_size(30)
         .build_cartesian_2d(
             0f64..losses.len() as f64,
-            0f64..losses.iter().copied().fold(0. / 0., f64::max),
+            0f64..losses.iter().copied().fold(f64::NAN, f64::max),
         )?;
 
     chart.configure_mesh().draw()?;
@@ -110,10 +107,20 @@ pub fn plot_losses(losses: &[f64], filename: &str) -> Result<()> {
     Ok(())
 }
 
-pub fn load_training_data(filename: &str) -> Result<Vec<(Vec<Value>, Value)>> {
+/// A training example: the input features and the target.
+pub type Sample<T = f64> = (Vec<Value<T>>, Value<T>);
+
+/// Loads `x0,x1,target` rows from a CSV file, converting them to the scalar type `T`.
+pub fn load_training_data<T: Float>(filename: &str) -> Result<Vec<Sample<T>>> {
     let file = File::open(filename)?;
     let mut reader = Reader::from_reader(file);
     let mut training_data = Vec::new();
+    // Labelled like variables, but data that backprop has no reason to differentiate
+    let constant = |x: f64, label: &str| {
+        let v = Value::new(T::from_f64(x), None, label.to_string(), None);
+        v.set_requires_grad(false);
+        v
+    };
 
     for result in reader.records() {
         let record = result?;
@@ -122,11 +129,8 @@ pub fn load_training_data(filename: &str) -> Result<Vec<(Vec<Value>, Value)>> {
         let target: f64 = record[2].parse()?;
 
         training_data.push((
-            vec![
-                Value::new(x0, None, "x0".to_string(), None),
-                Value::new(x1, None, "x1".to_string(), None),
-            ],
-            Value::new(target, None, "y".to_string(), None),
+            vec![constant(x0, "x0"), constant(x1, "x1")],
+            constant(target, "y"),
         ));
     }
 